@@ -0,0 +1,702 @@
+// Режимы работы программы, по одному на каждую подкоманду
+
+use super::board::{
+    self, Board, Color, DeadStones, Grid, InferError, Move, PlayError, ScoringRule,
+};
+use super::clock::{Clock, TimeControl};
+use super::config::{self, Command, Config};
+use super::game::Game;
+use super::gtp::{GtpEngine, StoneStatus};
+use super::katago::{self, AnalysisEngine, HumanProfile, Katago, Query};
+use super::sgf;
+use super::vision::{self, calibrate};
+use opencv::{core::Vector, highgui, imgcodecs, prelude::*, videoio};
+use std::path::{Path, PathBuf};
+use std::time::{Duration, Instant};
+use std::{fmt, fs, io};
+
+#[derive(Debug)]
+pub enum Error {
+    Io(io::Error),
+    OpenCv(opencv::Error),
+    Config(config::Error),
+    Engine(katago::Error),
+    Sgf(sgf::Error),
+    Calibrate(calibrate::Error),
+    IllegalMove(PlayError),
+    CameraNotOpened(i32),
+    // файл не удалось прочитать как картинку
+    InvalidImage(String),
+    BoardNotFound,
+}
+
+impl From<io::Error> for Error {
+    fn from(e: io::Error) -> Self {
+        Error::Io(e)
+    }
+}
+
+impl From<opencv::Error> for Error {
+    fn from(e: opencv::Error) -> Self {
+        Error::OpenCv(e)
+    }
+}
+
+impl From<config::Error> for Error {
+    fn from(e: config::Error) -> Self {
+        Error::Config(e)
+    }
+}
+
+impl From<katago::Error> for Error {
+    fn from(e: katago::Error) -> Self {
+        Error::Engine(e)
+    }
+}
+
+impl From<sgf::Error> for Error {
+    fn from(e: sgf::Error) -> Self {
+        Error::Sgf(e)
+    }
+}
+
+impl From<calibrate::Error> for Error {
+    fn from(e: calibrate::Error) -> Self {
+        Error::Calibrate(e)
+    }
+}
+
+impl From<PlayError> for Error {
+    fn from(e: PlayError) -> Self {
+        Error::IllegalMove(e)
+    }
+}
+
+impl fmt::Display for Error {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Error::Io(e) => write!(f, "{e}"),
+            Error::OpenCv(e) => write!(f, "opencv: {e}"),
+            Error::Config(e) => write!(f, "{e}"),
+            Error::Engine(e) => write!(f, "katago: {e}"),
+            Error::Sgf(e) => write!(f, "sgf: {e}"),
+            Error::Calibrate(e) => write!(f, "calibration failed: {e}"),
+            Error::IllegalMove(e) => write!(f, "{e}"),
+            Error::CameraNotOpened(camera) => write!(f, "camera {camera} is not opened"),
+            Error::InvalidImage(path) => write!(f, "{path} is not an image"),
+            Error::BoardNotFound => write!(f, "board not found"),
+        }
+    }
+}
+
+pub type Result<T> = std::result::Result<T, Error>;
+
+// Выполняет подкоманду, config_path нужен калибровке, чтобы сохранить результат
+pub fn run(command: &Command, config: Config, config_path: &str) -> Result<()> {
+    config.validate()?;
+    match command {
+        Command::Recognize { image } => recognize(&config, image),
+        Command::Watch { sgf } => watch(&config, sgf.as_deref()),
+        Command::Play {
+            color,
+            profile,
+            handicap,
+            main_time,
+            byo_yomi,
+            periods,
+            stones,
+            increment,
+            sgf,
+        } => {
+            let control = time_control(*main_time, *byo_yomi, *periods, *stones, *increment);
+            let handicap = handicap.map_or(0, usize::from);
+            play(&config, *color, *profile, handicap, control, sgf.as_deref())
+        }
+        Command::Analyze {
+            sgf,
+            time,
+            review,
+            visits,
+            image,
+        } => {
+            let visits = review.then_some(*visits);
+            let time = Duration::from_secs(*time);
+            analyze(&config, sgf, time, visits, image.as_deref())
+        }
+        Command::Calibrate => {
+            let calibrated = calibrate_camera(&config)?;
+            // в файл попадает только калибровка, параметры командной строки там не сохраняются
+            let mut saved = Config::load_or_default(config_path)?;
+            saved.vision = calibrated.with_dumps_of(&saved.vision);
+            saved.save(config_path)?;
+            println!("calibration saved to {config_path}");
+            Ok(())
+        }
+        Command::Replay { dir, sgf } => replay(&config, dir, sgf.as_deref()),
+    }
+}
+
+// Калибровка с камеры, подсказки пользователю печатаются здесь, а кадры показывает calibrate::run
+fn calibrate_camera(config: &Config) -> Result<vision::Settings> {
+    let size = config.app.board_size;
+    let stones = calibrate::calibration_stones(size)?;
+    let list = |color| {
+        let points: Vec<String> = (0..size * size)
+            .map(|idx| board::Position::new(idx % size, idx / size))
+            .filter(|&pos| stones.stone(pos) == Some(color))
+            .map(|pos| pos.to_gtp())
+            .collect();
+        points.join(" ")
+    };
+    println!("remove all stones from the board and press space");
+    println!("then put black stones on {}", list(Color::Black));
+    println!("and white stones on {}, press space", list(Color::White));
+    let mut cam = open_camera(&config.app)?;
+    Ok(calibrate::run(&config.vision, &mut cam, size)?)
+}
+
+// Контроль времени из опций play: stones задаёт канадское бёё-ёми, increment Фишера
+fn time_control(
+    main: Option<u64>,
+    byo_yomi: Option<u64>,
+    periods: u32,
+    stones: Option<u32>,
+    increment: Option<u64>,
+) -> Option<TimeControl> {
+    let main = Duration::from_secs(main.unwrap_or(0));
+    match (byo_yomi, stones, increment) {
+        (Some(period), Some(stones), _) => Some(TimeControl::Canadian {
+            main,
+            period: Duration::from_secs(period),
+            stones,
+        }),
+        (Some(period), None, _) => Some(TimeControl::ByoYomi {
+            main,
+            period: Duration::from_secs(period),
+            periods,
+        }),
+        (None, _, Some(increment)) => Some(TimeControl::Fischer {
+            main,
+            increment: Duration::from_secs(increment),
+        }),
+        (None, _, None) if !main.is_zero() => Some(TimeControl::Absolute { main }),
+        (None, _, None) => None,
+    }
+}
+
+fn copy_dir_all(src: impl AsRef<Path>, dst: impl AsRef<Path>) -> io::Result<()> {
+    fs::create_dir_all(&dst)?;
+    for entry in fs::read_dir(src)? {
+        let entry = entry?;
+        let ty = entry.file_type()?;
+        if ty.is_dir() {
+            copy_dir_all(entry.path(), dst.as_ref().join(entry.file_name()))?;
+        } else {
+            fs::copy(entry.path(), dst.as_ref().join(entry.file_name()))?;
+        }
+    }
+    Ok(())
+}
+
+fn open_camera(app: &config::App) -> Result<videoio::VideoCapture> {
+    let mut cam = videoio::VideoCapture::new(app.camera, videoio::CAP_ANY)?;
+    if !cam.is_opened()? {
+        return Err(Error::CameraNotOpened(app.camera));
+    }
+    cam.set(videoio::CAP_PROP_FRAME_WIDTH, app.frame_width)?;
+    cam.set(videoio::CAP_PROP_FRAME_HEIGHT, app.frame_height)?;
+    Ok(cam)
+}
+
+fn load_image(path: &str) -> Result<Mat> {
+    let img = imgcodecs::imread(path, imgcodecs::IMREAD_COLOR)?;
+    if img.empty() {
+        return Err(Error::InvalidImage(String::from(path)));
+    }
+    Ok(img)
+}
+
+// Доска на кадре, None если рамка доски не нашлась
+fn recognize_frame(config: &Config, frame: &Mat) -> Result<Option<Board>> {
+    let settings = &config.vision;
+    if let Some(dir) = settings.dump_dir() {
+        imgcodecs::imwrite(&format!("{dir}original.jpg"), frame, &Vector::default())?;
+    }
+    let Some(border) = vision::find_board_border(settings, frame)? else {
+        return Ok(None);
+    };
+    let warped = vision::warp_board_by_border(settings, &border, frame)?;
+    let board = vision::find_stones(settings, &warped, config.app.board_size)?;
+    Ok(Some(board))
+}
+
+fn start_katago(config: &Config) -> Result<Katago> {
+    config.validate_katago()?;
+    let mut katago = Katago::new(config.katago.clone())?;
+    katago.wait_gtp_ready()?;
+    Ok(katago)
+}
+
+// Файл SGF по умолчанию называется по текущему времени
+fn save_sgf(path: Option<&str>, info: &sgf::GameInfo, moves: &[sgf::Record]) -> Result<()> {
+    let path = match path {
+        Some(path) => String::from(path),
+        None => format!("./{}.sgf", chrono::Local::now().format("%F_%H-%M-%S")),
+    };
+    sgf::save(&path, info, moves)?;
+    println!("game saved to {path}");
+    Ok(())
+}
+
+// Восстанавливает ходы по последовательности распознанных досок
+struct Recorder {
+    state: Board,
+    moves: Vec<sgf::Record>,
+    last_error: String,
+    errors: usize,
+    // куда распознавание складывает отладочные картинки текущего кадра
+    dump_dir: Option<String>,
+}
+
+impl Recorder {
+    fn new(config: &Config) -> Recorder {
+        Recorder {
+            state: Board::new_with_size(config.app.board_size),
+            moves: Vec::new(),
+            last_error: String::new(),
+            errors: 0,
+            dump_dir: config.vision.dump_dir().map(String::from),
+        }
+    }
+
+    fn update(&mut self, board: Board) {
+        match board::infer_move(&self.state, &board) {
+            Ok(Some(mv)) => {
+                println!("____________________________________________________");
+                println!("{}", mv);
+                self.moves.push((mv.color, Move::Play(mv.pos)));
+                self.state = board;
+                self.last_error.clear();
+            }
+            Ok(None) => {}
+            // игрок ещё не убрал взятые камни, ждём следующий кадр
+            Err(InferError::CapturesPending { .. }) => {}
+            Err(err) => {
+                // одну и ту же ошибку сохраняем только один раз
+                let error = err.to_string();
+                if error != self.last_error {
+                    self.errors += 1;
+                    if let Some(dir) = &self.dump_dir {
+                        let out_dir = format!("./error_{}", self.errors);
+                        let _ = copy_dir_all(dir, &out_dir);
+                    }
+                    println!("____________________________________________________");
+                    println!("{}", error);
+                    self.last_error = error;
+                }
+            }
+        }
+    }
+
+    fn save(&self, path: Option<&str>, komi: f32) -> Result<()> {
+        let mut info = sgf::GameInfo::new(self.state.size());
+        info.komi = komi;
+        save_sgf(path, &info, &self.moves)
+    }
+}
+
+fn recognize(config: &Config, image: &str) -> Result<()> {
+    let img = load_image(image)?;
+    let board = recognize_frame(config, &img)?.ok_or(Error::BoardNotFound)?;
+    println!("{}", board);
+    Ok(())
+}
+
+// Записывает партию с камеры, пока не нажат Esc
+fn watch(config: &Config, sgf_path: Option<&str>) -> Result<()> {
+    let mut recorder = Recorder::new(config);
+    let res = watch_camera(config, &mut recorder);
+    // записанные ходы сохраняются, даже если камера отказала посреди партии
+    recorder.save(sgf_path, config.app.komi)?;
+    res
+}
+
+fn watch_camera(config: &Config, recorder: &mut Recorder) -> Result<()> {
+    let mut cam = open_camera(&config.app)?;
+    highgui::named_window("Camera", highgui::WINDOW_NORMAL)?;
+    let mut frame = Mat::default();
+    loop {
+        cam.read(&mut frame)?;
+        if frame.empty() {
+            continue;
+        }
+        // плохой кадр пропускаем, следующий скорее всего распознается
+        match recognize_frame(config, &frame) {
+            Ok(Some(board)) => recorder.update(board),
+            Ok(None) => {}
+            Err(error) => println!("{error}"),
+        }
+        highgui::imshow("Camera", &frame)?;
+        if highgui::wait_key(10)? == 27 {
+            return Ok(());
+        }
+    }
+}
+
+// Кадры из каталога по порядку, как будто они пришли с камеры.
+// Кадры с номерами вместо имени (0.jpg, 1.jpg, ...) сортируются по номеру.
+fn replay(config: &Config, dir: &str, sgf_path: Option<&str>) -> Result<()> {
+    let mut frames: Vec<PathBuf> = fs::read_dir(dir)?
+        .map(|entry| entry.map(|entry| entry.path()))
+        .collect::<io::Result<_>>()?;
+    frames.retain(|path| {
+        path.extension()
+            .is_some_and(|ext| ext == "jpg" || ext == "jpeg" || ext == "png")
+    });
+    frames.sort_by_key(|path| {
+        let number = path
+            .file_stem()
+            .and_then(|stem| stem.to_str()?.parse::<u64>().ok());
+        (number.unwrap_or(u64::MAX), path.clone())
+    });
+
+    let mut recorder = Recorder::new(config);
+    for path in frames {
+        let name = path.to_string_lossy();
+        let img = load_image(&name)?;
+        match recognize_frame(config, &img)? {
+            Some(board) => {
+                println!("{name}");
+                recorder.update(board);
+            }
+            None => println!("{name}: board not found"),
+        }
+    }
+    match sgf_path {
+        Some(path) => recorder.save(Some(path), config.app.komi),
+        None => Ok(()),
+    }
+}
+
+// Игра человека с KataGo на настоящей доске: человек ставит свои камни сам,
+// а ходы движка показываются в консоли, и их нужно поставить на доску.
+// p пасует за человека, Esc прерывает партию.
+fn play(
+    config: &Config,
+    human: Color,
+    profile: Option<HumanProfile>,
+    handicap: usize,
+    control: Option<TimeControl>,
+    sgf_path: Option<&str>,
+) -> Result<()> {
+    let mut katago = start_katago(config)?;
+    if let Some(profile) = profile {
+        katago.set_human_profile(profile)?;
+    }
+    let stones = match handicap {
+        0 => Vec::new(),
+        // точки форы выбирает движок на пустой доске нужного размера
+        _ => {
+            katago.boardsize(config.app.board_size)?;
+            katago.clear_board()?;
+            katago.fixed_handicap(handicap)?
+        }
+    };
+    let mut game = Game::with_handicap(config.app.board_size, config.app.komi, stones);
+    game.set_ko_rule(config.app.ko_rule);
+    if let Some(control) = control {
+        game.set_clock(Clock::new(control));
+    }
+
+    let res = play_game(config, human, &mut game, &mut katago);
+    if let Some(result) = game.result() {
+        println!("result: {result}");
+    }
+    let mut info = sgf::GameInfo::new(game.size());
+    info.komi = game.komi();
+    info.handicap = game.handicap().to_vec();
+    info.result = game.result();
+    let (black, white) = match human {
+        Color::Black => (&mut info.black, &mut info.white),
+        Color::White => (&mut info.white, &mut info.black),
+    };
+    *black = String::from("Human");
+    *white = String::from("KataGo");
+    let moves: Vec<sgf::Record> = game.moves().iter().map(|r| (r.color, r.mv)).collect();
+    // партия сохраняется и тогда, когда её прервала ошибка движка или камеры
+    save_sgf(sgf_path, &info, &moves)?;
+    res
+}
+
+fn play_game(config: &Config, human: Color, game: &mut Game, katago: &mut Katago) -> Result<()> {
+    let mut cam = open_camera(&config.app)?;
+    highgui::named_window("Camera", highgui::WINDOW_NORMAL)?;
+    let mut frame = Mat::default();
+    // ход движка сделан или фора ещё не расставлена, а камней на доске нет
+    let mut waiting = false;
+    if game.moves().is_empty() && !game.handicap().is_empty() {
+        let stones: Vec<String> = game.handicap().iter().map(|pos| pos.to_gtp()).collect();
+        println!("place handicap: {}", stones.join(" "));
+        waiting = true;
+    }
+    let mut last_error = String::new();
+    println!("you play {human}: p to pass, Esc to stop");
+
+    loop {
+        if game.result().is_some() {
+            return Ok(());
+        }
+        let passes = game.moves().iter().rev().take_while(|r| r.mv == Move::Pass);
+        if passes.count() >= 2 {
+            return score(game, katago, config.app.scoring_rule);
+        }
+
+        let color = game.next_color();
+        if color != human && !waiting {
+            let mv = match game.genmove(katago, color) {
+                Ok(mv) => mv,
+                // движок перезапущен с позицией партии, просим ход ещё раз
+                Err(katago::Error::Timeout) => {
+                    println!("KataGo did not answer in time, asking again");
+                    continue;
+                }
+                Err(error) => return Err(error.into()),
+            };
+            println!("____________________________________________________");
+            match game.moves().last() {
+                Some(record) if !record.captured.is_empty() => {
+                    let captured: Vec<String> =
+                        record.captured.iter().map(|pos| pos.to_gtp()).collect();
+                    println!("{color}: {mv}, remove {}", captured.join(" "));
+                }
+                _ => println!("{color}: {mv}"),
+            }
+            waiting = matches!(mv, Move::Play(_));
+            continue;
+        }
+
+        cam.read(&mut frame)?;
+        if frame.empty() {
+            continue;
+        }
+        let recognized = match recognize_frame(config, &frame) {
+            Ok(recognized) => recognized,
+            Err(err) => {
+                let error = err.to_string();
+                if error != last_error {
+                    println!("{error}");
+                    last_error = error;
+                }
+                None
+            }
+        };
+        if let Some(board) = recognized {
+            if waiting {
+                if board::diff(&board, game.board()).is_empty() {
+                    waiting = false;
+                    println!("your move");
+                }
+            } else {
+                match board::infer_move(game.board(), &board) {
+                    Ok(Some(mv)) if mv.color == human => {
+                        game.play(human, mv.pos)?;
+                        println!("{human}: {}", mv.pos);
+                        last_error.clear();
+                    }
+                    Ok(Some(mv)) => {
+                        let error = format!("{} is not your color, you play {human}", mv.color);
+                        if error != last_error {
+                            println!("{error}");
+                            last_error = error;
+                        }
+                    }
+                    Ok(None) => {}
+                    Err(InferError::CapturesPending { .. }) => {}
+                    Err(err) => {
+                        let error = err.to_string();
+                        if error != last_error {
+                            println!("{error}");
+                            last_error = error;
+                        }
+                    }
+                }
+            }
+        }
+
+        highgui::imshow("Camera", &frame)?;
+        match highgui::wait_key(10)? {
+            27 => return Ok(()),
+            112 if !waiting => {
+                game.pass(human);
+                println!("{human}: pass");
+            }
+            _ => {}
+        }
+    }
+}
+
+// Подсчёт после двух пасов подряд, мёртвые камни определяет движок
+fn score(game: &mut Game, katago: &mut Katago, rule: ScoringRule) -> Result<()> {
+    game.sync(katago)?;
+    let dead = DeadStones::from(katago.final_status_list(StoneStatus::Dead)?);
+    let score = game.score(&dead, rule);
+    print!("{score}");
+    game.set_result(score.result());
+    Ok(())
+}
+
+// Разбор партии движком анализа: оценка после каждого хода и ход, который движок предпочёл бы
+fn review_moves(config: &Config, game: &Game, visits: Option<u32>) -> Result<()> {
+    config.validate_katago()?;
+    let mut engine = AnalysisEngine::new(config.katago.clone())?;
+    let moves: Vec<sgf::Record> = game
+        .moves()
+        .iter()
+        .filter(|r| r.mv != Move::Resign)
+        .map(|r| (r.color, r.mv))
+        .collect();
+    let mut query = Query::new("review", moves.clone());
+    query.initial_stones = game
+        .handicap()
+        .iter()
+        .map(|&pos| (Color::Black, pos))
+        .chain(game.setup().iter().copied())
+        .collect();
+    query.komi = game.komi();
+    query.board_size = game.size();
+    query.rules = String::from(match config.app.scoring_rule {
+        ScoringRule::Area => "chinese",
+        ScoringRule::Territory => "japanese",
+    });
+    query.analyze_turns = Some((0..=moves.len()).collect());
+    query.max_visits = visits;
+
+    // оценки с той стороны, что указана в reportAnalysisWinratesAs конфига анализа
+    let analyses = engine.analyze(&[query])?;
+    for pair in analyses.windows(2) {
+        let (before, after) = (&pair[0], &pair[1]);
+        let (color, mv) = moves[before.turn_number];
+        let best = match before.moves.iter().find(|info| info.order == 0) {
+            Some(info) => info.mv.to_string(),
+            None => String::from("-"),
+        };
+        println!(
+            "{:>3} {color} {mv}: winrate {:.1}% score {:+.1}, best {best}",
+            after.turn_number,
+            after.winrate * 100.,
+            after.score_lead
+        );
+    }
+    Ok(())
+}
+
+// Оценка последней позиции основного варианта партии из файла SGF
+// review задаёт число визитов для разбора всех ходов, Some(None) это число из конфига анализа
+fn analyze(
+    config: &Config,
+    path: &str,
+    time: Duration,
+    review: Option<Option<u32>>,
+    image: Option<&str>,
+) -> Result<()> {
+    let tree = sgf::load(path)?;
+    let mut game = Game::from_sgf(&tree)?;
+    if let Some(visits) = review {
+        review_moves(config, &game, visits)?;
+    }
+
+    let mut katago = start_katago(config)?;
+    game.sync(&mut katago)?;
+    let state = katago.check_sync(game.board())?;
+    println!("{state}");
+
+    let start = Instant::now();
+    let mut best = String::new();
+    katago.analyze(game.next_color(), Duration::from_millis(500), |infos| {
+        if let Some(info) = infos.iter().find(|info| info.order == 0) {
+            let pv: Vec<String> = info.pv.iter().map(|mv| mv.to_string()).collect();
+            best = format!(
+                "best {} winrate {:.1}% score {:.1} visits {} pv {}",
+                info.mv,
+                info.winrate * 100.,
+                info.score_lead,
+                info.visits,
+                pv.join(" ")
+            );
+            println!("{best}");
+        }
+        start.elapsed() < time
+    })?;
+
+    // точки, которые скорее всего достанутся каждому цвету
+    let ownership = katago.ownership(game.next_color(), Duration::from_secs(1))?;
+    let owned = |sign: f32| ownership.iter().filter(|(_, v)| **v * sign > 0.5).count();
+    println!("{best}");
+    println!("black area ~{} white area ~{}", owned(1.), owned(-1.));
+    if let Some(image) = image {
+        show_ownership(config, image, &ownership)?;
+    }
+    Ok(())
+}
+
+// Оценка владения поверх фотографии той же позиции, окно закрывается любой клавишей
+fn show_ownership(config: &Config, image: &str, ownership: &Grid<f32>) -> Result<()> {
+    let settings = &config.vision;
+    let img = load_image(image)?;
+    let border = vision::find_board_border(settings, &img)?.ok_or(Error::BoardNotFound)?;
+    let warped = vision::warp_board_by_border(settings, &border, &img)?;
+    let overlay = vision::draw_ownership(settings, &warped, ownership)?;
+    highgui::named_window("Ownership", highgui::WINDOW_NORMAL)?;
+    highgui::imshow("Ownership", &overlay)?;
+    highgui::wait_key(0)?;
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn secs(s: u64) -> Duration {
+        Duration::from_secs(s)
+    }
+
+    #[test]
+    fn time_control_from_options() {
+        assert_eq!(time_control(None, None, 5, None, None), None);
+        assert_eq!(
+            time_control(Some(600), None, 5, None, None),
+            Some(TimeControl::Absolute { main: secs(600) })
+        );
+        assert_eq!(
+            time_control(Some(600), Some(30), 5, None, None),
+            Some(TimeControl::ByoYomi {
+                main: secs(600),
+                period: secs(30),
+                periods: 5
+            })
+        );
+        assert_eq!(
+            time_control(Some(600), Some(300), 5, Some(25), None),
+            Some(TimeControl::Canadian {
+                main: secs(600),
+                period: secs(300),
+                stones: 25
+            })
+        );
+        assert_eq!(
+            time_control(Some(600), None, 5, None, Some(10)),
+            Some(TimeControl::Fischer {
+                main: secs(600),
+                increment: secs(10)
+            })
+        );
+        // Фишер без основного времени тоже имеет смысл
+        assert_eq!(
+            time_control(None, None, 5, None, Some(10)),
+            Some(TimeControl::Fischer {
+                main: secs(0),
+                increment: secs(10)
+            })
+        );
+    }
+}
@@ -0,0 +1,429 @@
+// Поддельный движок GTP для тестов без настоящего KataGo и его моделей.
+// Отвечает в том же формате, что и KataGo, ходы берёт из -moves или выбирает случайно,
+// kata-analyze присылает случайных кандидатов и владение по стоящим камням.
+//
+// fake_katago [-moves Q16,D4,pass] [-seed 42] [-think 500] [-crash-once file]
+//             [любые аргументы KataGo, они пропускаются]
+//
+// -think задаёт задержку ответа на genmove в миллисекундах, для проверки таймаутов.
+// -crash-once: если файл есть, движок удаляет его и падает на genmove, так что
+// перезапущенный движок уже не падает.
+
+use robogo::board::{Board, Cell, Color, KoRule, MAX_SIZE, Move, Position};
+use std::collections::HashMap;
+use std::fs;
+use std::io::{self, BufRead, Write};
+use std::str::FromStr;
+use std::sync::mpsc::{RecvTimeoutError, channel};
+use std::thread;
+use std::time::{Duration, SystemTime, UNIX_EPOCH};
+
+// Простой xorshift, чтобы не тянуть зависимость ради случайных ходов
+struct Random(u64);
+
+impl Random {
+    fn next(&mut self) -> u64 {
+        self.0 ^= self.0 << 13;
+        self.0 ^= self.0 >> 7;
+        self.0 ^= self.0 << 17;
+        self.0
+    }
+}
+
+struct Engine {
+    board: Board,
+    komi: f32,
+    next: Color,
+    move_num: u32,
+    // позиции до каждого хода, для undo
+    history: Vec<(Board, Color)>,
+    // заготовленные ответы на genmove, после них ходы случайные
+    script: Vec<Move>,
+    random: Random,
+    // значения kata-set-param, на игру они не влияют
+    params: HashMap<String, String>,
+}
+
+impl Engine {
+    fn new(script: Vec<Move>, seed: u64) -> Engine {
+        let mut board = Board::new_with_size(19);
+        board.set_ko_rule(KoRule::PositionalSuperko);
+        Engine {
+            board,
+            komi: 7.5,
+            next: Color::Black,
+            move_num: 0,
+            history: Vec::new(),
+            script,
+            // у xorshift нулевое состояние не меняется
+            random: Random(seed.max(1)),
+            params: HashMap::new(),
+        }
+    }
+
+    fn clear(&mut self, size: usize) {
+        let mut board = Board::new_with_size(size);
+        board.set_ko_rule(self.board.ko_rule());
+        self.board = board;
+        self.next = Color::Black;
+        self.move_num = 0;
+        self.history.clear();
+    }
+
+    fn play(&mut self, color: Color, mv: Move) -> Result<(), String> {
+        let before = (self.board.clone(), self.next);
+        match mv {
+            Move::Play(pos) => {
+                if !self.board.contains(pos) {
+                    return Err(String::from("illegal move"));
+                }
+                self.board
+                    .play(color, pos)
+                    .map_err(|e| format!("illegal move: {e}"))?;
+            }
+            Move::Pass => self.board.pass(color),
+            Move::Resign => return Ok(()),
+        }
+        self.history.push(before);
+        self.next = color.opposite();
+        self.move_num += 1;
+        Ok(())
+    }
+
+    fn undo(&mut self) -> Result<(), String> {
+        let (board, next) = self.history.pop().ok_or("cannot undo")?;
+        self.board = board;
+        self.next = next;
+        self.move_num -= 1;
+        Ok(())
+    }
+
+    fn random_move(&mut self, color: Color) -> Move {
+        let size = self.board.size();
+        let mut free: Vec<Position> = (0..size * size)
+            .map(|idx| Position::new(idx % size, idx / size))
+            .filter(|&pos| self.board.stone(pos).is_none())
+            .collect();
+        while !free.is_empty() {
+            let idx = (self.random.next() % free.len() as u64) as usize;
+            let pos = free.swap_remove(idx);
+            if self.board.check_move(color, pos).is_ok() {
+                return Move::Play(pos);
+            }
+        }
+        Move::Pass
+    }
+
+    fn genmove(&mut self, color: Color) -> Result<Move, String> {
+        let mv = if self.script.is_empty() {
+            self.random_move(color)
+        } else {
+            self.script.remove(0)
+        };
+        self.play(color, mv)?;
+        Ok(mv)
+    }
+
+    // Строка kata-analyze с двумя случайными кандидатами, владение по стоящим камням
+    fn analyze(&mut self, color: Color, ownership: bool) -> String {
+        let mut res = String::new();
+        for order in 0..2 {
+            let mv = self.random_move(color);
+            res.push_str(&format!(
+                "info move {mv} visits {} winrate 0.5 scoreLead 0.0 prior 0.1 order {order} pv {mv} ",
+                100 - order * 50
+            ));
+        }
+        if ownership {
+            // значения идут с верхней строки, с точки зрения того, чей ход
+            res.push_str("ownership");
+            let size = self.board.size();
+            for y in (0..size).rev() {
+                for x in 0..size {
+                    let value = match self.board.stone(Position::new(x, y)) {
+                        Some(stone) if stone == color => 1.,
+                        Some(_) => -1.,
+                        None => 0.,
+                    };
+                    res.push_str(&format!(" {value:.6}"));
+                }
+            }
+        }
+        res.trim_end().to_string()
+    }
+
+    // Вывод как у showboard в KataGo
+    fn showboard(&self) -> String {
+        let size = self.board.size();
+        let mut res = format!(
+            "MoveNum: {} HASH: {:032X}\n",
+            self.move_num,
+            self.board.hash()
+        );
+        res.push_str("  ");
+        for x in 0..size {
+            res.push_str(&format!(" {}", Position::new(x, 0).column_letter()));
+        }
+        res.push('\n');
+        for y in (0..size).rev() {
+            res.push_str(&format!("{:>2}", y + 1));
+            for x in 0..size {
+                let ch = match self.board.stone(Position::new(x, y)) {
+                    Some(Color::Black) => 'X',
+                    Some(Color::White) => 'O',
+                    None => '.',
+                };
+                res.push(' ');
+                res.push(ch);
+            }
+            res.push('\n');
+        }
+        res.push_str(&format!("Next player: {}\n", self.next));
+        res.push_str(&format!(
+            "Rules: {{\"friendlyPassOk\":false,\"hasButton\":false,\"ko\":\"POSITIONAL\",\
+             \"komi\":{},\"scoring\":\"AREA\",\"suicide\":true,\"tax\":\"NONE\",\
+             \"whiteHandicapBonus\":\"N-1\"}}\n",
+            self.komi
+        ));
+        res.push_str(&format!(
+            "B stones captured: {}\n",
+            self.board.captured(Color::Black)
+        ));
+        res.push_str(&format!(
+            "W stones captured: {}",
+            self.board.captured(Color::White)
+        ));
+        res
+    }
+
+    fn execute(&mut self, cmd: &str, args: &[&str]) -> Result<String, String> {
+        let arg = |idx: usize| args.get(idx).copied().ok_or("syntax error");
+        match cmd {
+            "protocol_version" => Ok(String::from("2")),
+            "name" => Ok(String::from("KataGo")),
+            "version" => Ok(String::from("fake")),
+            "list_commands" => Ok(COMMANDS.join("\n")),
+            "known_command" => Ok(COMMANDS.contains(&arg(0)?).to_string()),
+            "boardsize" => {
+                let size = arg(0)?.parse::<usize>().map_err(|_| "unacceptable size")?;
+                if !(2..=MAX_SIZE).contains(&size) {
+                    return Err(String::from("unacceptable size"));
+                }
+                self.clear(size);
+                Ok(String::new())
+            }
+            "clear_board" => {
+                self.clear(self.board.size());
+                Ok(String::new())
+            }
+            "komi" => {
+                self.komi = arg(0)?.parse::<f32>().map_err(|_| "syntax error")?;
+                Ok(String::new())
+            }
+            "play" => {
+                let color = Color::from_str(arg(0)?).map_err(|_| "syntax error")?;
+                let mv = Move::from_str(arg(1)?).map_err(|_| "syntax error")?;
+                self.play(color, mv)?;
+                Ok(String::new())
+            }
+            "genmove" => {
+                let color = Color::from_str(arg(0)?).map_err(|_| "syntax error")?;
+                Ok(self.genmove(color)?.to_string())
+            }
+            "set_free_handicap" => {
+                if !self.history.is_empty() || args.is_empty() {
+                    return Err(String::from("board not empty"));
+                }
+                for word in args {
+                    let pos = Position::from_str(word).map_err(|_| "syntax error")?;
+                    if !self.board.contains(pos) || self.board.stone(pos).is_some() {
+                        return Err(String::from("illegal handicap stone"));
+                    }
+                    self.board.set(pos, Cell::black_stone());
+                }
+                self.next = Color::White;
+                Ok(String::new())
+            }
+            "undo" => {
+                self.undo()?;
+                Ok(String::new())
+            }
+            "showboard" => Ok(self.showboard()),
+            // время не учитывается, ходы и так мгновенные
+            "time_settings" | "kgs-time_settings" | "kata-time_settings" | "time_left" => {
+                Ok(String::new())
+            }
+            "kata-set-param" => {
+                self.params
+                    .insert(String::from(arg(0)?), String::from(arg(1)?));
+                Ok(String::new())
+            }
+            "kata-get-param" => self
+                .params
+                .get(arg(0)?)
+                .cloned()
+                .ok_or_else(|| String::from("unknown parameter")),
+            _ => Err(String::from("unknown command")),
+        }
+    }
+}
+
+const COMMANDS: &[&str] = &[
+    "protocol_version",
+    "name",
+    "version",
+    "list_commands",
+    "known_command",
+    "boardsize",
+    "clear_board",
+    "komi",
+    "play",
+    "genmove",
+    "set_free_handicap",
+    "undo",
+    "showboard",
+    "time_settings",
+    "kgs-time_settings",
+    "kata-time_settings",
+    "time_left",
+    "kata-set-param",
+    "kata-get-param",
+    "kata-analyze",
+    "quit",
+];
+
+struct Options {
+    script: Vec<Move>,
+    seed: u64,
+    think: Duration,
+    crash_once: Option<String>,
+}
+
+fn parse_args() -> Options {
+    let mut options = Options {
+        script: Vec::new(),
+        seed: SystemTime::now()
+            .duration_since(UNIX_EPOCH)
+            .map(|time| time.as_nanos() as u64)
+            .unwrap_or(1),
+        think: Duration::ZERO,
+        crash_once: None,
+    };
+    let args: Vec<String> = std::env::args().skip(1).collect();
+    let mut idx = 0;
+    while idx < args.len() {
+        match (args[idx].as_str(), args.get(idx + 1)) {
+            ("-moves", Some(moves)) => {
+                options.script = moves
+                    .split(',')
+                    .map(|mv| Move::from_str(mv).unwrap_or_else(|_| panic!("bad move {mv}")))
+                    .collect();
+                idx += 1;
+            }
+            ("-seed", Some(value)) => {
+                options.seed = value.parse().expect("bad seed");
+                idx += 1;
+            }
+            ("-think", Some(value)) => {
+                options.think = Duration::from_millis(value.parse().expect("bad think time"));
+                idx += 1;
+            }
+            ("-crash-once", Some(file)) => {
+                options.crash_once = Some(file.clone());
+                idx += 1;
+            }
+            _ => {}
+        }
+        idx += 1;
+    }
+    options
+}
+
+fn main() -> io::Result<()> {
+    let options = parse_args();
+    let mut engine = Engine::new(options.script, options.seed);
+    eprintln!("GTP ready, beginning main protocol loop");
+
+    // stdin читается в своём потоке, чтобы новая команда могла прервать kata-analyze
+    let (sender, receiver) = channel();
+    thread::spawn(move || {
+        for line in io::stdin().lock().lines().map_while(|line| line.ok()) {
+            if sender.send(line).is_err() {
+                break;
+            }
+        }
+    });
+
+    let stdout = io::stdout();
+    let mut out = stdout.lock();
+    let mut pending = None;
+    loop {
+        let line = match pending.take() {
+            Some(line) => line,
+            None => match receiver.recv() {
+                Ok(line) => line,
+                Err(_) => break,
+            },
+        };
+        let mut words = line.split_whitespace().peekable();
+        // необязательный номер команды повторяется в ответе
+        let id = match words.peek() {
+            Some(word) if word.bytes().all(|ch| ch.is_ascii_digit()) => words.next(),
+            _ => None,
+        };
+        let Some(cmd) = words.next() else {
+            continue;
+        };
+        let args: Vec<&str> = words.collect();
+        let id = id.unwrap_or("");
+        if cmd == "quit" {
+            write!(out, "={id}\n\n")?;
+            out.flush()?;
+            break;
+        }
+        if cmd == "kata-analyze" {
+            let Some(color) = args.first().and_then(|color| Color::from_str(color).ok()) else {
+                write!(out, "?{id} syntax error\n\n")?;
+                out.flush()?;
+                continue;
+            };
+            let interval = args
+                .get(1)
+                .and_then(|interval| interval.parse::<u64>().ok())
+                .unwrap_or(100);
+            let ownership = args.windows(2).any(|pair| pair == ["ownership", "true"]);
+            writeln!(out, "={id}")?;
+            // анализ идёт, пока не придёт любая строка, она потом выполняется как команда
+            loop {
+                writeln!(out, "{}", engine.analyze(color, ownership))?;
+                out.flush()?;
+                match receiver.recv_timeout(Duration::from_millis(interval * 10)) {
+                    Ok(line) => {
+                        pending = Some(line);
+                        break;
+                    }
+                    Err(RecvTimeoutError::Timeout) => {}
+                    Err(RecvTimeoutError::Disconnected) => return Ok(()),
+                }
+            }
+            writeln!(out)?;
+            out.flush()?;
+            continue;
+        }
+        if cmd == "genmove" {
+            if let Some(file) = &options.crash_once
+                && fs::remove_file(file).is_ok()
+            {
+                std::process::exit(1);
+            }
+            thread::sleep(options.think);
+        }
+        match engine.execute(cmd, &args) {
+            Ok(text) if text.is_empty() => write!(out, "={id}\n\n")?,
+            Ok(text) => write!(out, "={id} {text}\n\n")?,
+            Err(text) => write!(out, "?{id} {text}\n\n")?,
+        }
+        out.flush()?;
+    }
+    Ok(())
+}
@@ -0,0 +1,159 @@
+use super::{MAX_SIZE, ParsePositionError, Position};
+
+// В GTP и в привычной записи "Q16" буква I пропускается, чтобы не путать её с J
+const LETTERS: &[u8] = b"ABCDEFGHJKLMNOPQRSTUVWXYZ";
+
+fn letter_index(letter: u8) -> Option<usize> {
+    let letter = letter.to_ascii_uppercase();
+    LETTERS.iter().position(|&l| l == letter)
+}
+
+impl Position {
+    // "Q16": столбец буквой без I, строка с единицы снизу
+    pub fn to_gtp(self) -> String {
+        format!("{}{}", LETTERS[self.x] as char, self.y + 1)
+    }
+
+    // По GTP координаты не зависят от регистра
+    pub fn from_gtp(s: &str) -> Result<Position, ParsePositionError> {
+        let s = s.trim();
+        let letter = *s.as_bytes().first().ok_or(ParsePositionError)?;
+        let x = letter_index(letter).ok_or(ParsePositionError)?;
+        let row = s
+            .get(1..)
+            .ok_or(ParsePositionError)?
+            .trim()
+            .parse::<usize>()
+            .map_err(|_| ParsePositionError)?;
+        if row == 0 || row > MAX_SIZE {
+            return Err(ParsePositionError);
+        }
+        Ok(Position::new(x, row - 1))
+    }
+
+    // "pd": столбец и строка буквами с 'a', строки отсчитываются сверху, поэтому нужен размер доски
+    pub fn to_sgf(self, size: usize) -> String {
+        let x = (b'a' + self.x as u8) as char;
+        let y = (b'a' + (size - self.y - 1) as u8) as char;
+        format!("{}{}", x, y)
+    }
+
+    pub fn from_sgf(s: &str, size: usize) -> Result<Position, ParsePositionError> {
+        let bytes = s.as_bytes();
+        if bytes.len() != 2 {
+            return Err(ParsePositionError);
+        }
+        let x = bytes[0].wrapping_sub(b'a') as usize;
+        let row = bytes[1].wrapping_sub(b'a') as usize;
+        if x >= size || row >= size || size > MAX_SIZE {
+            return Err(ParsePositionError);
+        }
+        Ok(Position::new(x, size - row - 1))
+    }
+
+    // "16-4": номера столбца и строки с единицы от левого нижнего угла
+    pub fn to_numeric(self) -> String {
+        format!("{}-{}", self.x + 1, self.y + 1)
+    }
+
+    pub fn from_numeric(s: &str) -> Result<Position, ParsePositionError> {
+        let (x, y) = s.trim().split_once('-').ok_or(ParsePositionError)?;
+        let x = x.trim().parse::<usize>().map_err(|_| ParsePositionError)?;
+        let y = y.trim().parse::<usize>().map_err(|_| ParsePositionError)?;
+        if x == 0 || y == 0 || x > MAX_SIZE || y > MAX_SIZE {
+            return Err(ParsePositionError);
+        }
+        Ok(Position::new(x - 1, y - 1))
+    }
+
+    // Буква столбца для подписей на доске
+    pub fn column_letter(self) -> char {
+        LETTERS[self.x] as char
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn gtp_round_trip() {
+        for x in 0..25 {
+            for y in [0, 8, 24] {
+                let pos = Position::new(x, y);
+                assert_eq!(Position::from_gtp(&pos.to_gtp()).unwrap(), pos);
+            }
+        }
+    }
+
+    #[test]
+    fn gtp_skips_i() {
+        assert_eq!(Position::new(7, 0).to_gtp(), "H1");
+        assert_eq!(Position::new(8, 0).to_gtp(), "J1");
+        assert_eq!(Position::new(15, 15).to_gtp(), "Q16");
+        assert_eq!(Position::new(24, 24).to_gtp(), "Z25");
+        assert_eq!(Position::from_gtp("t19").unwrap(), Position::new(18, 18));
+        assert_eq!(Position::new(8, 3).column_letter(), 'J');
+    }
+
+    #[test]
+    fn gtp_rejects_invalid() {
+        for s in ["A0", "I5", "", "Q", "5Q", "Q-1", "QQ16", "A26", "Z100"] {
+            assert!(Position::from_gtp(s).is_err(), "{s}");
+        }
+    }
+
+    #[test]
+    fn sgf_round_trip() {
+        for size in [9, 13, 19, 25] {
+            for x in 0..size {
+                for y in 0..size {
+                    let pos = Position::new(x, y);
+                    assert_eq!(Position::from_sgf(&pos.to_sgf(size), size).unwrap(), pos);
+                }
+            }
+        }
+    }
+
+    #[test]
+    fn sgf_rows_count_from_top() {
+        assert_eq!(Position::from_sgf("aa", 19).unwrap(), Position::new(0, 18));
+        assert_eq!(Position::from_sgf("ss", 19).unwrap(), Position::new(18, 0));
+        assert_eq!(Position::from_sgf("pd", 19).unwrap().to_gtp(), "Q16");
+        assert_eq!(Position::new(0, 0).to_sgf(9), "ai");
+        // tt за пределами 19x19, в записи ходов это пас
+        assert!(Position::from_sgf("tt", 19).is_err());
+        assert_eq!(Position::from_sgf("tt", 21).unwrap(), Position::new(19, 1));
+        for s in ["", "a", "abc", "A1", "jj"] {
+            assert!(Position::from_sgf(s, 9).is_err(), "{s}");
+        }
+    }
+
+    #[test]
+    fn rejects_beyond_max_size() {
+        assert!(Position::from_sgf("aa", 26).is_err());
+        assert!(Position::from_numeric("26-1").is_err());
+        assert!(Position::from_numeric("1-26").is_err());
+        assert_eq!(Position::from_numeric("25-25").unwrap().to_gtp(), "Z25");
+    }
+
+    #[test]
+    #[should_panic]
+    fn new_beyond_max_size_panics() {
+        Position::new(MAX_SIZE, 0);
+    }
+
+    #[test]
+    fn numeric_round_trip() {
+        let pos = Position::new(15, 3);
+        assert_eq!(pos.to_numeric(), "16-4");
+        assert_eq!(Position::from_numeric("16-4").unwrap(), pos);
+        assert_eq!(
+            Position::from_numeric(" 1 - 1 ").unwrap(),
+            Position::new(0, 0)
+        );
+        for s in ["0-1", "1-0", "1", "a-b", "-1-2"] {
+            assert!(Position::from_numeric(s).is_err(), "{s}");
+        }
+    }
+}
@@ -0,0 +1,65 @@
+use super::{MAX_SIZE, Position};
+
+// Значения по точкам доски любой формы, в том числе прямоугольной.
+// Строка y=0 нижняя, как и у Position.
+#[derive(Clone, Debug, PartialEq)]
+pub struct Grid<T> {
+    width: usize,
+    height: usize,
+    cells: Vec<T>,
+}
+
+impl<T: Clone> Grid<T> {
+    pub fn new(width: usize, height: usize, value: T) -> Grid<T> {
+        assert!(width <= MAX_SIZE && height <= MAX_SIZE);
+        Grid {
+            width,
+            height,
+            cells: vec![value; width * height],
+        }
+    }
+}
+
+impl<T> Grid<T> {
+    pub fn width(&self) -> usize {
+        self.width
+    }
+
+    pub fn height(&self) -> usize {
+        self.height
+    }
+
+    pub fn contains(&self, pos: Position) -> bool {
+        pos.x() < self.width && pos.y() < self.height
+    }
+
+    pub fn get(&self, pos: Position) -> Option<&T> {
+        if !self.contains(pos) {
+            return None;
+        }
+        self.cells.get(pos.y() * self.width + pos.x())
+    }
+
+    pub fn set(&mut self, pos: Position, value: T) {
+        if self.contains(pos) {
+            self.cells[pos.y() * self.width + pos.x()] = value;
+        }
+    }
+
+    // Все точки с их значениями, снизу вверх и слева направо
+    pub fn iter(&self) -> impl Iterator<Item = (Position, &T)> {
+        self.cells
+            .iter()
+            .enumerate()
+            .map(|(idx, value)| (Position::new(idx % self.width, idx / self.width), value))
+    }
+
+    // Новая сетка того же размера со значениями f(значение)
+    pub fn map<U>(&self, f: impl Fn(&T) -> U) -> Grid<U> {
+        Grid {
+            width: self.width,
+            height: self.height,
+            cells: self.cells.iter().map(f).collect(),
+        }
+    }
+}
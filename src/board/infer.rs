@@ -0,0 +1,286 @@
+use super::{Action, Board, Color, PlayError, Position, diff};
+use std::fmt;
+use std::fmt::Display;
+
+// Ход, восстановленный по разнице двух распознанных досок
+pub struct InferredMove {
+    pub color: Color,
+    pub pos: Position,
+    pub captured: Vec<Position>,
+}
+
+#[derive(Debug)]
+pub enum InferError {
+    SizeMismatch,
+    // появилось сразу несколько новых камней
+    MultipleMoves(Vec<(Position, Color)>),
+    // камни пропали с доски, хотя их никто не брал
+    Disappeared(Vec<(Position, Color)>),
+    // камень переложили с одного места на другое
+    Moved {
+        color: Color,
+        from: Position,
+        to: Position,
+    },
+    // ход сделан, но взятые камни ещё лежат на доске
+    CapturesPending {
+        color: Color,
+        pos: Position,
+        pending: Vec<Position>,
+    },
+    Illegal {
+        color: Color,
+        pos: Position,
+        error: PlayError,
+    },
+}
+
+// Определяет единственный ход, который переводит prev в next.
+// Ok(None) означает, что на доске ничего не изменилось.
+pub fn infer_move(prev: &Board, next: &Board) -> Result<Option<InferredMove>, InferError> {
+    if prev.size != next.size {
+        return Err(InferError::SizeMismatch);
+    }
+
+    let mut added = Vec::new();
+    let mut removed = Vec::new();
+    for action in diff(prev, next) {
+        match action {
+            Action::Add(pos, color) => added.push((pos, color)),
+            Action::Remove(pos, color) => removed.push((pos, color)),
+        }
+    }
+
+    if added.is_empty() {
+        if removed.is_empty() {
+            return Ok(None);
+        }
+        return Err(InferError::Disappeared(removed));
+    }
+    if added.len() > 1 {
+        return Err(InferError::MultipleMoves(added));
+    }
+
+    let (pos, color) = added[0];
+    // ход никогда не снимает камни своего цвета, значит камень переложили
+    if let [(from, removed_color)] = removed[..]
+        && removed_color == color
+    {
+        return Err(InferError::Moved {
+            color,
+            from,
+            to: pos,
+        });
+    }
+
+    let captured = prev
+        .check_move(color, pos)
+        .map_err(|error| InferError::Illegal { color, pos, error })?;
+
+    let unexpected: Vec<(Position, Color)> = removed
+        .into_iter()
+        .filter(|(removed_pos, _)| !captured.contains(removed_pos))
+        .collect();
+    if !unexpected.is_empty() {
+        return Err(InferError::Disappeared(unexpected));
+    }
+
+    let pending: Vec<Position> = captured
+        .iter()
+        .filter(|&&captured_pos| next.stone(captured_pos).is_some())
+        .copied()
+        .collect();
+    if !pending.is_empty() {
+        return Err(InferError::CapturesPending {
+            color,
+            pos,
+            pending,
+        });
+    }
+
+    Ok(Some(InferredMove {
+        color,
+        pos,
+        captured,
+    }))
+}
+
+fn write_stones(f: &mut fmt::Formatter<'_>, stones: &[(Position, Color)]) -> fmt::Result {
+    for (pos, color) in stones {
+        write!(f, " {} {}", color, pos)?;
+    }
+    Ok(())
+}
+
+impl Display for InferredMove {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{} plays {}", self.color, self.pos)?;
+        if !self.captured.is_empty() {
+            write!(f, ", captured {} stones", self.captured.len())?;
+        }
+        Ok(())
+    }
+}
+
+impl Display for InferError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            InferError::SizeMismatch => write!(f, "boards have different sizes"),
+            InferError::MultipleMoves(stones) => {
+                write!(f, "several stones added:")?;
+                write_stones(f, stones)
+            }
+            InferError::Disappeared(stones) => {
+                write!(f, "stones disappeared:")?;
+                write_stones(f, stones)
+            }
+            InferError::Moved { color, from, to } => {
+                write!(f, "{} stone moved from {} to {}", color, from, to)
+            }
+            InferError::CapturesPending {
+                color,
+                pos,
+                pending,
+            } => write!(
+                f,
+                "{} plays {}, {} captured stones still on board",
+                color,
+                pos,
+                pending.len()
+            ),
+            InferError::Illegal { color, pos, error } => {
+                write!(f, "{} {} is illegal: {}", color, pos, error)
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::board::Cell;
+
+    fn pos(s: &str) -> Position {
+        Position::from_gtp(s).unwrap()
+    }
+
+    fn setup(black: &[&str], white: &[&str]) -> Board {
+        let mut board = Board::new_with_size(9);
+        for s in black {
+            board.set(pos(s), Cell::black_stone());
+        }
+        for s in white {
+            board.set(pos(s), Cell::white_stone());
+        }
+        board
+    }
+
+    #[test]
+    fn no_change() {
+        let board = setup(&["C3"], &["D4"]);
+        assert!(matches!(infer_move(&board, &board.clone()), Ok(None)));
+    }
+
+    #[test]
+    fn capture_move() {
+        let prev = setup(&["A2", "C2", "B1"], &["B2"]);
+        let next = setup(&["A2", "C2", "B1", "B3"], &[]);
+        let mv = infer_move(&prev, &next).unwrap().unwrap();
+        assert_eq!(mv.color, Color::Black);
+        assert_eq!(mv.pos, pos("B3"));
+        assert_eq!(mv.captured, vec![pos("B2")]);
+    }
+
+    #[test]
+    fn size_mismatch() {
+        let prev = Board::new_with_size(9);
+        let next = Board::new_with_size(13);
+        assert!(matches!(
+            infer_move(&prev, &next),
+            Err(InferError::SizeMismatch)
+        ));
+    }
+
+    #[test]
+    fn multiple_moves() {
+        let prev = setup(&[], &[]);
+        let next = setup(&["C3"], &["D4"]);
+        match infer_move(&prev, &next) {
+            Err(InferError::MultipleMoves(added)) => assert_eq!(added.len(), 2),
+            _ => panic!("expected MultipleMoves"),
+        }
+    }
+
+    #[test]
+    fn disappeared_without_move() {
+        let prev = setup(&["C3"], &["D4"]);
+        let next = setup(&["C3"], &[]);
+        match infer_move(&prev, &next) {
+            Err(InferError::Disappeared(stones)) => {
+                assert_eq!(stones, vec![(pos("D4"), Color::White)])
+            }
+            _ => panic!("expected Disappeared"),
+        }
+    }
+
+    #[test]
+    fn disappeared_besides_capture() {
+        // ход ничего не берёт, а белый камень в другом месте пропал
+        let prev = setup(&["C3"], &["G7"]);
+        let next = setup(&["C3", "D4"], &[]);
+        match infer_move(&prev, &next) {
+            Err(InferError::Disappeared(stones)) => {
+                assert_eq!(stones, vec![(pos("G7"), Color::White)])
+            }
+            _ => panic!("expected Disappeared"),
+        }
+    }
+
+    #[test]
+    fn moved_stone() {
+        let prev = setup(&["C3"], &[]);
+        let next = setup(&["D4"], &[]);
+        match infer_move(&prev, &next) {
+            Err(InferError::Moved { color, from, to }) => {
+                assert_eq!((color, from, to), (Color::Black, pos("C3"), pos("D4")))
+            }
+            _ => panic!("expected Moved"),
+        }
+    }
+
+    #[test]
+    fn captures_pending() {
+        let prev = setup(&["A2", "C2", "B1"], &["B2"]);
+        let next = setup(&["A2", "C2", "B1", "B3"], &["B2"]);
+        match infer_move(&prev, &next) {
+            Err(InferError::CapturesPending {
+                color,
+                pos: at,
+                pending,
+            }) => {
+                assert_eq!((color, at), (Color::Black, pos("B3")));
+                assert_eq!(pending, vec![pos("B2")]);
+            }
+            _ => panic!("expected CapturesPending"),
+        }
+    }
+
+    #[test]
+    fn illegal_move() {
+        let prev = setup(&[], &["A2", "B1"]);
+        let next = setup(&["A1"], &["A2", "B1"]);
+        match infer_move(&prev, &next) {
+            Err(InferError::Illegal {
+                color,
+                pos: at,
+                error,
+            }) => {
+                assert_eq!(
+                    (color, at, error),
+                    (Color::Black, pos("A1"), PlayError::Suicide)
+                )
+            }
+            _ => panic!("expected Illegal"),
+        }
+    }
+}
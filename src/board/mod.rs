@@ -0,0 +1,728 @@
+mod coord;
+mod grid;
+mod infer;
+mod score;
+mod zobrist;
+
+pub use grid::Grid;
+pub use infer::{InferError, InferredMove, infer_move};
+pub use score::{DeadStones, Score, ScoringRule};
+
+use serde::{Deserialize, Serialize};
+use std::fmt;
+use std::fmt::Display;
+use std::str::FromStr;
+
+#[derive(PartialEq, Eq, Clone, Copy, Debug)]
+pub enum Color {
+    Black,
+    White,
+}
+
+impl Color {
+    pub fn opposite(self) -> Color {
+        match self {
+            Color::Black => Color::White,
+            Color::White => Color::Black,
+        }
+    }
+}
+
+#[derive(PartialEq, Eq, Clone, Copy, Debug)]
+pub struct Cell(Option<Color>);
+
+impl Cell {
+    pub fn empty() -> Cell {
+        Cell(None)
+    }
+
+    pub fn black_stone() -> Cell {
+        Cell(Some(Color::Black))
+    }
+
+    pub fn white_stone() -> Cell {
+        Cell(Some(Color::White))
+    }
+}
+
+impl From<Color> for Cell {
+    fn from(color: Color) -> Self {
+        Cell(Some(color))
+    }
+}
+
+// Больше линий не бывает: в GTP столбцы обозначаются буквами без I
+pub const MAX_SIZE: usize = 25;
+
+// Обе координаты всегда меньше MAX_SIZE, поэтому у любой точки есть запись в GTP
+#[derive(PartialEq, Eq, Clone, Copy, Debug)]
+pub struct Position {
+    x: usize,
+    y: usize,
+}
+
+impl Position {
+    // Паникует на координатах за MAX_SIZE, внешние данные проверяют from_gtp, from_sgf и т.п.
+    pub fn new(x: usize, y: usize) -> Position {
+        assert!(
+            x < MAX_SIZE && y < MAX_SIZE,
+            "position ({x}, {y}) is outside of any board"
+        );
+        Position { x: x, y: y }
+    }
+
+    pub fn x(&self) -> usize {
+        self.x
+    }
+
+    pub fn y(&self) -> usize {
+        self.y
+    }
+}
+
+#[derive(PartialEq, Eq, Clone, Copy, Debug)]
+pub enum Move {
+    Play(Position),
+    Pass,
+    Resign,
+}
+
+// Итог партии в той же записи, что используют SGF (RE) и GTP (final_score)
+#[derive(PartialEq, Clone, Copy, Debug)]
+pub enum GameResult {
+    // победа с перевесом в очках
+    Score(Color, f32),
+    // победа без указания перевеса
+    Win(Color),
+    Resign(Color),
+    Time(Color),
+    Forfeit(Color),
+    Draw,
+    Void,
+    Unknown,
+}
+
+pub struct ParseResultError;
+
+impl FromStr for GameResult {
+    type Err = ParseResultError;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let s = s.trim();
+        match s {
+            "0" | "Draw" | "Jigo" => return Ok(GameResult::Draw),
+            "Void" => return Ok(GameResult::Void),
+            "?" => return Ok(GameResult::Unknown),
+            _ => {}
+        }
+        let (winner, reason) = s.split_once('+').ok_or(ParseResultError)?;
+        let winner = match winner {
+            "B" => Color::Black,
+            "W" => Color::White,
+            _ => return Err(ParseResultError),
+        };
+        match reason {
+            "" => Ok(GameResult::Win(winner)),
+            "R" | "Resign" => Ok(GameResult::Resign(winner)),
+            "T" | "Time" => Ok(GameResult::Time(winner)),
+            "F" | "Forfeit" => Ok(GameResult::Forfeit(winner)),
+            margin => {
+                let margin = margin.parse::<f32>().map_err(|_| ParseResultError)?;
+                Ok(GameResult::Score(winner, margin))
+            }
+        }
+    }
+}
+
+#[derive(Debug)]
+pub struct ParsePositionError;
+
+#[derive(Debug)]
+pub struct ParseColorError;
+
+// Цвет в записи GTP: "b", "black", "w" или "white" в любом регистре
+impl FromStr for Color {
+    type Err = ParseColorError;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s.trim().to_lowercase().as_str() {
+            "b" | "black" => Ok(Color::Black),
+            "w" | "white" => Ok(Color::White),
+            _ => Err(ParseColorError),
+        }
+    }
+}
+
+// Ход в записи GTP: "Q16", "pass" или "resign"
+impl FromStr for Move {
+    type Err = ParsePositionError;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let s = s.trim();
+        if s.eq_ignore_ascii_case("pass") {
+            return Ok(Move::Pass);
+        }
+        if s.eq_ignore_ascii_case("resign") {
+            return Ok(Move::Resign);
+        }
+        Ok(Move::Play(Position::from_gtp(s)?))
+    }
+}
+
+// Разбирает запись "Q16", в том числе ответы движка
+impl FromStr for Position {
+    type Err = ParsePositionError;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        Position::from_gtp(s)
+    }
+}
+
+// Почему ход не может быть сыгран
+#[derive(PartialEq, Eq, Clone, Copy, Debug)]
+pub enum PlayError {
+    OutOfBoard,
+    Occupied,
+    Suicide,
+    Ko,
+    Superko,
+}
+
+// Какой вариант правила ко проверяется при ходе
+#[derive(PartialEq, Eq, Clone, Copy, Debug, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum KoRule {
+    // запрещено только немедленное взятие обратно одного камня
+    Simple,
+    // запрещено повторять любую позицию, которая уже была на доске
+    PositionalSuperko,
+    // запрещено повторять позицию с тем же игроком на очереди
+    SituationalSuperko,
+}
+
+#[derive(Clone)]
+pub struct Board {
+    board: Vec<Cell>,
+    size: usize,
+    // точка, в которую запрещено ходить из-за простого ко
+    ko: Option<Position>,
+    ko_rule: KoRule,
+    black_captured: u32,
+    white_captured: u32,
+    // хэш Зобриста текущей позиции, обновляется инкрементально
+    hash: u64,
+    // хэши всех позиций партии и цвет, сделавший ход, который к ним привёл
+    history: Vec<(u64, Option<Color>)>,
+}
+
+impl Board {
+    pub fn new_with_size(size: usize) -> Board {
+        assert!(size <= MAX_SIZE, "board size {size} is above {MAX_SIZE}");
+        let mut res = Board {
+            board: Vec::new(),
+            size: size,
+            ko: None,
+            ko_rule: KoRule::Simple,
+            black_captured: 0,
+            white_captured: 0,
+            hash: 0,
+            history: vec![(0, None)],
+        };
+        res.board.resize_with(size * size, Cell::empty);
+        return res;
+    }
+    pub fn default() -> Board {
+        Board::new_with_size(19)
+    }
+
+    pub fn pos2idx(&self, pos: Position) -> usize {
+        if pos.x >= self.size {
+            panic!(
+                "invalid x position where board size={} and x={}",
+                self.size, pos.x
+            );
+        }
+        if pos.y >= self.size {
+            panic!(
+                "invalid y position where board size={} and y={}",
+                self.size, pos.y
+            );
+        }
+        pos.y * self.size + pos.x
+    }
+    pub fn size(&self) -> usize {
+        self.size
+    }
+
+    pub fn contains(&self, pos: Position) -> bool {
+        pos.x < self.size && pos.y < self.size
+    }
+
+    pub fn stone(&self, pos: Position) -> Option<Color> {
+        let Cell(stone) = self.board[self.pos2idx(pos)];
+        stone
+    }
+
+    // Сколько камней этого цвета было снято с доски
+    pub fn captured(&self, color: Color) -> u32 {
+        match color {
+            Color::Black => self.black_captured,
+            Color::White => self.white_captured,
+        }
+    }
+
+    // Точка, в которую следующим ходом нельзя ходить из-за ко
+    pub fn ko(&self) -> Option<Position> {
+        self.ko
+    }
+
+    pub fn ko_rule(&self) -> KoRule {
+        self.ko_rule
+    }
+
+    pub fn set_ko_rule(&mut self, rule: KoRule) {
+        self.ko_rule = rule;
+    }
+
+    pub fn hash(&self) -> u64 {
+        self.hash
+    }
+
+    // Ставит камень без проверки правил, используется при распознавании и разборе showboard.
+    // История позиций после этого начинается заново с текущей.
+    pub fn set(&mut self, pos: Position, cell: Cell) {
+        let idx = self.pos2idx(pos);
+        self.put(idx, cell);
+        self.ko = None;
+        self.history.clear();
+        self.history.push((self.hash, None));
+    }
+
+    // Единственное место, где меняется содержимое клетки, чтобы хэш всегда был актуален
+    fn put(&mut self, idx: usize, cell: Cell) {
+        if let Cell(Some(old)) = self.board[idx] {
+            self.hash ^= zobrist::stone_key(idx, old);
+        }
+        if let Cell(Some(new)) = cell {
+            self.hash ^= zobrist::stone_key(idx, new);
+        }
+        self.board[idx] = cell;
+    }
+
+    // Была ли уже такая позиция с учётом правила суперко
+    fn repeats_position(&self, hash: u64, color: Color) -> bool {
+        match self.ko_rule {
+            KoRule::Simple => false,
+            KoRule::PositionalSuperko => self.history.iter().any(|&(h, _)| h == hash),
+            KoRule::SituationalSuperko => self
+                .history
+                .iter()
+                .any(|&(h, mover)| h == hash && mover == Some(color)),
+        }
+    }
+
+    // Проверяет ход, не меняя доску. Возвращает позиции камней, которые будут сняты.
+    pub fn check_move(&self, color: Color, pos: Position) -> Result<Vec<Position>, PlayError> {
+        self.clone().play(color, pos)
+    }
+
+    // Ход по правилам: снимает захваченные группы, запрещает самоубийство и простое ко.
+    // Возвращает позиции снятых камней.
+    pub fn play(&mut self, color: Color, pos: Position) -> Result<Vec<Position>, PlayError> {
+        if !self.contains(pos) {
+            return Err(PlayError::OutOfBoard);
+        }
+        if self.stone(pos).is_some() {
+            return Err(PlayError::Occupied);
+        }
+        if self.ko == Some(pos) {
+            return Err(PlayError::Ko);
+        }
+
+        let idx = self.pos2idx(pos);
+        self.put(idx, Cell::from(color));
+
+        let enemy = color.opposite();
+        let mut captured = Vec::new();
+        for neighbor in self.neighbors(pos) {
+            if self.stone(neighbor) != Some(enemy) || captured.contains(&neighbor) {
+                continue;
+            }
+            let (group, liberties) = self.group(neighbor);
+            if liberties == 0 {
+                captured.extend(group);
+            }
+        }
+
+        if captured.is_empty() {
+            let (_, liberties) = self.group(pos);
+            if liberties == 0 {
+                self.put(idx, Cell::empty());
+                return Err(PlayError::Suicide);
+            }
+        }
+
+        for &stone in &captured {
+            let idx = self.pos2idx(stone);
+            self.put(idx, Cell::empty());
+        }
+
+        if self.repeats_position(self.hash, color) {
+            // откатываем ход
+            for &stone in &captured {
+                let idx = self.pos2idx(stone);
+                self.put(idx, Cell::from(enemy));
+            }
+            self.put(idx, Cell::empty());
+            return Err(PlayError::Superko);
+        }
+        self.history.push((self.hash, Some(color)));
+        match enemy {
+            Color::Black => self.black_captured += captured.len() as u32,
+            Color::White => self.white_captured += captured.len() as u32,
+        }
+
+        // ко возникает, когда одиночный камень взял ровно один камень и остался с одной свободой
+        self.ko = None;
+        if captured.len() == 1 {
+            let (group, liberties) = self.group(pos);
+            if group.len() == 1 && liberties == 1 {
+                self.ko = Some(captured[0]);
+            }
+        }
+        Ok(captured)
+    }
+
+    // Пас снимает запрет ко и передаёт очередь, что важно для ситуационного суперко
+    pub fn pass(&mut self, color: Color) {
+        self.ko = None;
+        self.history.push((self.hash, Some(color)));
+    }
+
+    pub fn neighbors(&self, pos: Position) -> Vec<Position> {
+        let mut res = Vec::with_capacity(4);
+        if pos.x > 0 {
+            res.push(Position::new(pos.x - 1, pos.y));
+        }
+        if pos.x + 1 < self.size {
+            res.push(Position::new(pos.x + 1, pos.y));
+        }
+        if pos.y > 0 {
+            res.push(Position::new(pos.x, pos.y - 1));
+        }
+        if pos.y + 1 < self.size {
+            res.push(Position::new(pos.x, pos.y + 1));
+        }
+        res
+    }
+
+    // Группа камней, связанная с камнем в pos, и количество её свобод
+    pub fn group(&self, pos: Position) -> (Vec<Position>, usize) {
+        let color = match self.stone(pos) {
+            Some(color) => color,
+            None => return (Vec::new(), 0),
+        };
+        let mut visited = vec![false; self.board.len()];
+        let mut liberties = vec![false; self.board.len()];
+        let mut stack = vec![pos];
+        let mut group = Vec::new();
+        visited[self.pos2idx(pos)] = true;
+        while let Some(current) = stack.pop() {
+            group.push(current);
+            for neighbor in self.neighbors(current) {
+                let idx = self.pos2idx(neighbor);
+                match self.stone(neighbor) {
+                    None => liberties[idx] = true,
+                    Some(stone) if stone == color && !visited[idx] => {
+                        visited[idx] = true;
+                        stack.push(neighbor);
+                    }
+                    _ => {}
+                }
+            }
+        }
+        let liberties_count = liberties.iter().filter(|&&free| free).count();
+        (group, liberties_count)
+    }
+}
+
+pub enum Action {
+    Add(Position, Color),
+    Remove(Position, Color),
+}
+
+pub fn diff(from: &Board, to: &Board) -> Vec<Action> {
+    let mut res = Vec::new();
+    if from.size != to.size {
+        return res;
+    }
+
+    for y in 0..from.size {
+        for x in 0..from.size {
+            let pos = Position::new(x, y);
+            let idx = from.pos2idx(pos);
+            let Cell(f) = &from.board[idx];
+            let Cell(t) = &to.board[idx];
+            if f != t {
+                if let Some(stone) = f {
+                    res.push(Action::Remove(pos, stone.clone()));
+                }
+                if let Some(stone) = t {
+                    res.push(Action::Add(pos, stone.clone()));
+                }
+            }
+        }
+    }
+    res
+}
+
+impl Display for Cell {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Cell(Some(stone)) => match stone {
+                Color::Black => write!(f, "B"),
+                Color::White => write!(f, "W"),
+            },
+            Cell(None) => write!(f, "."),
+        }
+    }
+}
+
+impl Display for Board {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        for row in (0..self.size).rev() {
+            write!(f, "{:>2}| ", row + 1)?;
+            for col in 0..self.size {
+                let idx = self.pos2idx(Position::new(col, row));
+                write!(f, "{} ", self.board[idx])?;
+            }
+            writeln!(f)?;
+        }
+        write!(f, "    ")?;
+        for _ in 0..self.size {
+            write!(f, "__")?;
+        }
+        writeln!(f)?;
+        write!(f, "    ")?;
+        for col in 0..self.size {
+            write!(f, "{} ", Position::new(col, 0).column_letter())?;
+        }
+        writeln!(f)?;
+        Ok(())
+    }
+}
+
+impl Display for Position {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{}", self.to_gtp())
+    }
+}
+
+impl Display for GameResult {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let letter = |color: &Color| match color {
+            Color::Black => "B",
+            Color::White => "W",
+        };
+        match self {
+            GameResult::Score(winner, margin) => write!(f, "{}+{}", letter(winner), margin),
+            GameResult::Win(winner) => write!(f, "{}+", letter(winner)),
+            GameResult::Resign(winner) => write!(f, "{}+R", letter(winner)),
+            GameResult::Time(winner) => write!(f, "{}+T", letter(winner)),
+            GameResult::Forfeit(winner) => write!(f, "{}+F", letter(winner)),
+            GameResult::Draw => write!(f, "0"),
+            GameResult::Void => write!(f, "Void"),
+            GameResult::Unknown => write!(f, "?"),
+        }
+    }
+}
+
+impl Display for Move {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Move::Play(pos) => write!(f, "{}", pos),
+            Move::Pass => write!(f, "pass"),
+            Move::Resign => write!(f, "resign"),
+        }
+    }
+}
+
+impl Display for Color {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Color::Black => write!(f, "Black"),
+            Color::White => write!(f, "White"),
+        }
+    }
+}
+
+impl Display for PlayError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            PlayError::OutOfBoard => write!(f, "position is out of board"),
+            PlayError::Occupied => write!(f, "position is occupied"),
+            PlayError::Suicide => write!(f, "suicide move"),
+            PlayError::Ko => write!(f, "move violates ko"),
+            PlayError::Superko => write!(f, "move repeats previous position"),
+        }
+    }
+}
+
+impl Display for Action {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Action::Add(pos, stone) => write!(f, "Add to {} {} stone", pos, stone),
+            Action::Remove(pos, stone) => write!(f, "Remove from {} {} stone", pos, stone),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn pos(s: &str) -> Position {
+        Position::from_gtp(s).unwrap()
+    }
+
+    fn setup(size: usize, black: &[&str], white: &[&str]) -> Board {
+        let mut board = Board::new_with_size(size);
+        for s in black {
+            board.set(pos(s), Cell::black_stone());
+        }
+        for s in white {
+            board.set(pos(s), Cell::white_stone());
+        }
+        board
+    }
+
+    // . X O .
+    // X O . O
+    // . X O .
+    fn ko_shape() -> Board {
+        setup(5, &["B3", "A2", "B1"], &["C3", "B2", "D2", "C1"])
+    }
+
+    #[test]
+    fn captures_single_stone() {
+        let mut board = setup(5, &["A2", "C2", "B1"], &["B2"]);
+        assert_eq!(board.play(Color::Black, pos("B3")), Ok(vec![pos("B2")]));
+        assert_eq!(board.stone(pos("B2")), None);
+        assert_eq!(board.captured(Color::White), 1);
+        assert_eq!(board.captured(Color::Black), 0);
+    }
+
+    #[test]
+    fn captures_several_groups() {
+        let mut board = setup(5, &["A2", "C2", "D1"], &["A1", "C1"]);
+        let mut captured = board.play(Color::Black, pos("B1")).unwrap();
+        captured.sort_by_key(|p| (p.x(), p.y()));
+        assert_eq!(captured, vec![pos("A1"), pos("C1")]);
+        assert_eq!(board.captured(Color::White), 2);
+    }
+
+    #[test]
+    fn rejects_suicide() {
+        let mut board = setup(5, &[], &["A2", "B1"]);
+        assert_eq!(board.play(Color::Black, pos("A1")), Err(PlayError::Suicide));
+        assert_eq!(board.stone(pos("A1")), None);
+        assert_eq!(board.hash(), setup(5, &[], &["A2", "B1"]).hash());
+    }
+
+    #[test]
+    fn capture_without_liberties_is_not_suicide() {
+        let mut board = setup(5, &["B2", "C1", "A3"], &["A2", "B1"]);
+        let captured = board.play(Color::Black, pos("A1")).unwrap();
+        assert_eq!(captured.len(), 2);
+        assert_eq!(board.stone(pos("A1")), Some(Color::Black));
+    }
+
+    #[test]
+    fn ko_ban_and_reset_after_other_move() {
+        let mut board = ko_shape();
+        assert_eq!(board.play(Color::Black, pos("C2")), Ok(vec![pos("B2")]));
+        assert_eq!(board.ko(), Some(pos("B2")));
+        assert_eq!(board.play(Color::White, pos("B2")), Err(PlayError::Ko));
+        assert_eq!(board.stone(pos("B2")), None);
+
+        board.play(Color::White, pos("E5")).unwrap();
+        assert_eq!(board.ko(), None);
+        board.play(Color::Black, pos("E4")).unwrap();
+        assert_eq!(board.play(Color::White, pos("B2")), Ok(vec![pos("C2")]));
+    }
+
+    #[test]
+    fn ko_ban_reset_after_pass() {
+        let mut board = ko_shape();
+        board.play(Color::Black, pos("C2")).unwrap();
+        board.pass(Color::White);
+        assert_eq!(board.ko(), None);
+        board.pass(Color::Black);
+        assert!(board.check_move(Color::White, pos("B2")).is_ok());
+    }
+
+    #[test]
+    fn snapback_capture_is_not_ko() {
+        // взятие двух камней не создаёт ко
+        let mut board = setup(5, &["A2", "B3", "C2"], &["B2", "B1"]);
+        board.play(Color::Black, pos("C1")).unwrap();
+        board.play(Color::White, pos("E5")).unwrap();
+        assert_eq!(board.play(Color::Black, pos("A1")).map(|c| c.len()), Ok(2));
+        assert_eq!(board.ko(), None);
+    }
+
+    // Ко с пасами между взятиями: простое ко это разрешает, суперко нет.
+    // Если пасует и белый, исходную позицию уже создавал белый, что ловит и ситуационное суперко.
+    fn ko_after_passes(
+        rule: KoRule,
+        white_passed_first: bool,
+    ) -> (Board, Result<Vec<Position>, PlayError>) {
+        let mut board = ko_shape();
+        board.set_ko_rule(rule);
+        board.pass(Color::Black);
+        if white_passed_first {
+            board.pass(Color::White);
+        }
+        board.play(Color::Black, pos("C2")).unwrap();
+        board.pass(Color::White);
+        board.pass(Color::Black);
+        let res = board.play(Color::White, pos("B2"));
+        (board, res)
+    }
+
+    #[test]
+    fn positional_superko() {
+        let (_, res) = ko_after_passes(KoRule::Simple, false);
+        assert_eq!(res, Ok(vec![pos("C2")]));
+        let (board, res) = ko_after_passes(KoRule::PositionalSuperko, false);
+        assert_eq!(res, Err(PlayError::Superko));
+        // отклонённый ход ничего не меняет на доске
+        assert_eq!(board.stone(pos("B2")), None);
+        assert_eq!(board.stone(pos("C2")), Some(Color::Black));
+        assert_eq!(board.captured(Color::Black), 0);
+        let mut expected = ko_shape();
+        expected.play(Color::Black, pos("C2")).unwrap();
+        assert_eq!(board.hash(), expected.hash());
+    }
+
+    #[test]
+    fn situational_superko() {
+        let (_, res) = ko_after_passes(KoRule::SituationalSuperko, false);
+        assert_eq!(res, Ok(vec![pos("C2")]));
+        let (_, res) = ko_after_passes(KoRule::SituationalSuperko, true);
+        assert_eq!(res, Err(PlayError::Superko));
+    }
+
+    #[test]
+    fn rejects_out_of_board_and_occupied() {
+        let mut board = setup(5, &["C3"], &[]);
+        assert_eq!(
+            board.play(Color::White, Position::new(5, 0)),
+            Err(PlayError::OutOfBoard)
+        );
+        assert_eq!(
+            board.play(Color::White, pos("C3")),
+            Err(PlayError::Occupied)
+        );
+    }
+}
@@ -0,0 +1,260 @@
+use super::{Board, Color, GameResult, Position};
+use serde::{Deserialize, Serialize};
+use std::fmt;
+use std::fmt::Display;
+
+#[derive(PartialEq, Eq, Clone, Copy, Debug, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum ScoringRule {
+    // китайские правила: камни на доске плюс территория
+    Area,
+    // японские правила: территория плюс пленные
+    Territory,
+}
+
+// Группы, отмеченные мёртвыми перед подсчётом
+#[derive(Default, Clone)]
+pub struct DeadStones {
+    stones: Vec<Position>,
+}
+
+impl DeadStones {
+    pub fn new() -> DeadStones {
+        DeadStones::default()
+    }
+
+    pub fn contains(&self, pos: Position) -> bool {
+        self.stones.contains(&pos)
+    }
+
+    pub fn stones(&self) -> &[Position] {
+        &self.stones
+    }
+
+    // Отмечает всю группу, в которую входит камень
+    pub fn mark_group(&mut self, board: &Board, pos: Position) {
+        let (group, _) = board.group(pos);
+        for stone in group {
+            if !self.contains(stone) {
+                self.stones.push(stone);
+            }
+        }
+    }
+
+    pub fn unmark_group(&mut self, board: &Board, pos: Position) {
+        let (group, _) = board.group(pos);
+        self.stones.retain(|stone| !group.contains(stone));
+    }
+
+    // Щелчок по группе: живая становится мёртвой и наоборот
+    pub fn toggle_group(&mut self, board: &Board, pos: Position) {
+        if self.contains(pos) {
+            self.unmark_group(board, pos);
+        } else {
+            self.mark_group(board, pos);
+        }
+    }
+}
+
+impl From<Vec<Position>> for DeadStones {
+    fn from(stones: Vec<Position>) -> Self {
+        DeadStones { stones }
+    }
+}
+
+pub struct Score {
+    pub rule: ScoringRule,
+    pub komi: f32,
+    pub black_territory: u32,
+    pub white_territory: u32,
+    // живые камни на доске
+    pub black_stones: u32,
+    pub white_stones: u32,
+    // камни соперника, взятые за партию и мёртвые на доске
+    pub black_prisoners: u32,
+    pub white_prisoners: u32,
+}
+
+impl Score {
+    pub fn black(&self) -> f32 {
+        match self.rule {
+            ScoringRule::Area => (self.black_stones + self.black_territory) as f32,
+            ScoringRule::Territory => (self.black_territory + self.black_prisoners) as f32,
+        }
+    }
+
+    pub fn white(&self) -> f32 {
+        let points = match self.rule {
+            ScoringRule::Area => self.white_stones + self.white_territory,
+            ScoringRule::Territory => self.white_territory + self.white_prisoners,
+        };
+        points as f32 + self.komi
+    }
+
+    pub fn result(&self) -> GameResult {
+        let margin = self.black() - self.white();
+        if margin > 0. {
+            GameResult::Score(Color::Black, margin)
+        } else if margin < 0. {
+            GameResult::Score(Color::White, -margin)
+        } else {
+            GameResult::Draw
+        }
+    }
+}
+
+impl Board {
+    // Подсчёт очков в конце партии. Мёртвые камни снимаются и считаются пленными,
+    // пустые области, окружённые только одним цветом, считаются его территорией.
+    pub fn score(&self, dead: &DeadStones, komi: f32, rule: ScoringRule) -> Score {
+        let mut score = Score {
+            rule,
+            komi,
+            black_territory: 0,
+            white_territory: 0,
+            black_stones: 0,
+            white_stones: 0,
+            black_prisoners: self.captured(Color::White),
+            white_prisoners: self.captured(Color::Black),
+        };
+
+        // после снятия мёртвых камней в клетке остаётся только живой камень
+        let mut alive = vec![None; self.board.len()];
+        for y in 0..self.size {
+            for x in 0..self.size {
+                let pos = Position::new(x, y);
+                match self.stone(pos) {
+                    Some(color) if dead.contains(pos) => match color {
+                        Color::Black => score.white_prisoners += 1,
+                        Color::White => score.black_prisoners += 1,
+                    },
+                    Some(color) => {
+                        alive[self.pos2idx(pos)] = Some(color);
+                        match color {
+                            Color::Black => score.black_stones += 1,
+                            Color::White => score.white_stones += 1,
+                        }
+                    }
+                    None => {}
+                }
+            }
+        }
+
+        let mut visited = vec![false; self.board.len()];
+        for start in 0..self.board.len() {
+            if visited[start] || alive[start].is_some() {
+                continue;
+            }
+            // обходим пустую область и запоминаем, какие цвета её окружают
+            let mut region = 0;
+            let mut borders_black = false;
+            let mut borders_white = false;
+            let mut stack = vec![Position::new(start % self.size, start / self.size)];
+            visited[start] = true;
+            while let Some(current) = stack.pop() {
+                region += 1;
+                for neighbor in self.neighbors(current) {
+                    let idx = self.pos2idx(neighbor);
+                    match alive[idx] {
+                        Some(Color::Black) => borders_black = true,
+                        Some(Color::White) => borders_white = true,
+                        None if !visited[idx] => {
+                            visited[idx] = true;
+                            stack.push(neighbor);
+                        }
+                        None => {}
+                    }
+                }
+            }
+            match (borders_black, borders_white) {
+                (true, false) => score.black_territory += region,
+                (false, true) => score.white_territory += region,
+                _ => {}
+            }
+        }
+        score
+    }
+}
+
+impl Display for Score {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        writeln!(f, "black: {}", self.black())?;
+        writeln!(f, "white: {} (komi {})", self.white(), self.komi)?;
+        writeln!(f, "result: {}", self.result())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::board::Cell;
+
+    fn pos(s: &str) -> Position {
+        Position::from_gtp(s).unwrap()
+    }
+
+    // Чёрная стена на B, белая на D, столбец C нейтральный, на A мёртвый белый камень
+    fn walls() -> (Board, DeadStones) {
+        let mut board = Board::new_with_size(5);
+        for row in 1..=5 {
+            board.set(pos(&format!("B{row}")), Cell::black_stone());
+            board.set(pos(&format!("D{row}")), Cell::white_stone());
+        }
+        board.set(pos("A3"), Cell::white_stone());
+        let mut dead = DeadStones::new();
+        dead.mark_group(&board, pos("A3"));
+        (board, dead)
+    }
+
+    #[test]
+    fn area_scoring() {
+        let (board, dead) = walls();
+        let score = board.score(&dead, 0.5, ScoringRule::Area);
+        assert_eq!((score.black_stones, score.white_stones), (5, 5));
+        // A3 после снятия мёртвого камня тоже территория, столбец C ничей
+        assert_eq!((score.black_territory, score.white_territory), (5, 5));
+        assert_eq!(score.black(), 10.);
+        assert_eq!(score.white(), 10.5);
+        assert_eq!(score.result(), GameResult::Score(Color::White, 0.5));
+    }
+
+    #[test]
+    fn territory_scoring() {
+        let (board, dead) = walls();
+        let score = board.score(&dead, 0.5, ScoringRule::Territory);
+        assert_eq!((score.black_prisoners, score.white_prisoners), (1, 0));
+        assert_eq!(score.black(), 6.);
+        assert_eq!(score.white(), 5.5);
+        assert_eq!(score.result(), GameResult::Score(Color::Black, 0.5));
+    }
+
+    #[test]
+    fn captured_stones_are_prisoners() {
+        let mut board = Board::new_with_size(5);
+        for s in ["A2", "C2", "B1"] {
+            board.set(pos(s), Cell::black_stone());
+        }
+        board.set(pos("B2"), Cell::white_stone());
+        board.play(Color::Black, pos("B3")).unwrap();
+        let score = board.score(&DeadStones::new(), 0., ScoringRule::Territory);
+        assert_eq!(score.black_prisoners, 1);
+        // на доске только чёрные, вся пустота их
+        assert_eq!(score.black_territory, 21);
+        assert_eq!(score.black(), 22.);
+        let score = board.score(&DeadStones::new(), 22., ScoringRule::Area);
+        assert_eq!(score.result(), GameResult::Score(Color::Black, 3.));
+    }
+
+    #[test]
+    fn toggle_dead_group() {
+        let mut board = Board::new_with_size(5);
+        board.set(pos("C3"), Cell::white_stone());
+        board.set(pos("C4"), Cell::white_stone());
+        let mut dead = DeadStones::new();
+        dead.toggle_group(&board, pos("C3"));
+        assert!(dead.contains(pos("C4")));
+        assert_eq!(dead.stones().len(), 2);
+        dead.toggle_group(&board, pos("C4"));
+        assert!(dead.stones().is_empty());
+    }
+}
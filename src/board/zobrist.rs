@@ -0,0 +1,20 @@
+use super::Color;
+
+// Ключи Зобриста не хранятся таблицей, а вычисляются из индекса клетки
+// смешивающей функцией splitmix64, так они одинаковы для досок любого размера
+const SEED: u64 = 0x5851_f42d_4c95_7f2d;
+
+fn mix(mut z: u64) -> u64 {
+    z = z.wrapping_add(0x9e37_79b9_7f4a_7c15);
+    z = (z ^ (z >> 30)).wrapping_mul(0xbf58_476d_1ce4_e5b9);
+    z = (z ^ (z >> 27)).wrapping_mul(0x94d0_49bb_1331_11eb);
+    z ^ (z >> 31)
+}
+
+pub fn stone_key(idx: usize, color: Color) -> u64 {
+    let color_bit = match color {
+        Color::Black => 0,
+        Color::White => 1,
+    };
+    mix(SEED ^ ((idx as u64) << 1 | color_bit))
+}
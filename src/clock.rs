@@ -0,0 +1,322 @@
+use super::board::Color;
+use std::time::{Duration, Instant};
+
+// Контроль времени партии
+#[derive(PartialEq, Eq, Clone, Copy, Debug)]
+pub enum TimeControl {
+    // без ограничения времени
+    Unlimited,
+    // только основное время
+    Absolute {
+        main: Duration,
+    },
+    // после основного времени periods периодов по period на каждый ход
+    ByoYomi {
+        main: Duration,
+        period: Duration,
+        periods: u32,
+    },
+    // после основного времени stones ходов за каждый period
+    Canadian {
+        main: Duration,
+        period: Duration,
+        stones: u32,
+    },
+    // к основному времени добавляется increment после каждого хода
+    Fischer {
+        main: Duration,
+        increment: Duration,
+    },
+}
+
+// Остаток времени в том виде, в каком его ждёт команда time_left
+#[derive(PartialEq, Eq, Clone, Copy, Debug)]
+pub struct TimeLeft {
+    pub time: Duration,
+    // ходов до конца периода в канадском, периодов в бёё-ёми, 0 пока идёт основное время
+    pub stones: u32,
+}
+
+#[derive(Clone, Copy, Debug)]
+struct Side {
+    main: Duration,
+    // остаток текущего периода в канадском
+    period: Duration,
+    // оставшиеся периоды в бёё-ёми
+    periods: u32,
+    // оставшиеся ходы текущего периода в канадском
+    stones: u32,
+    // время вышло
+    flagged: bool,
+}
+
+impl Side {
+    fn new(control: TimeControl) -> Side {
+        let (main, period, periods, stones) = match control {
+            TimeControl::Unlimited => (Duration::ZERO, Duration::ZERO, 0, 0),
+            TimeControl::Absolute { main } | TimeControl::Fischer { main, .. } => {
+                (main, Duration::ZERO, 0, 0)
+            }
+            TimeControl::ByoYomi {
+                main,
+                period,
+                periods,
+            } => (main, period, periods, 0),
+            TimeControl::Canadian {
+                main,
+                period,
+                stones,
+            } => (main, period, 0, stones),
+        };
+        Side {
+            main,
+            period,
+            periods,
+            stones,
+            flagged: false,
+        }
+    }
+
+    // Списывает время, потраченное на ход. Основное время тратится первым.
+    fn spend(&mut self, control: TimeControl, elapsed: Duration) {
+        let overtime = elapsed.saturating_sub(self.main);
+        self.main = self.main.saturating_sub(elapsed);
+        if overtime.is_zero() {
+            return;
+        }
+        match control {
+            TimeControl::Unlimited => {}
+            TimeControl::Absolute { .. } | TimeControl::Fischer { .. } => self.flagged = true,
+            TimeControl::ByoYomi { period, .. } => {
+                // каждый целиком потраченный период сгорает, следующий начинается заново
+                let lost = (overtime.as_millis() / period.as_millis().max(1)) as u32;
+                self.flagged |= lost >= self.periods;
+                self.periods = self.periods.saturating_sub(lost);
+            }
+            TimeControl::Canadian { .. } => {
+                self.flagged |= overtime > self.period;
+                self.period = self.period.saturating_sub(overtime);
+            }
+        }
+    }
+
+    // Ход сделан: добавка по Фишеру и счёт ходов периода в канадском
+    fn moved(&mut self, control: TimeControl) {
+        match control {
+            TimeControl::Fischer { increment, .. } => self.main += increment,
+            TimeControl::Canadian { period, stones, .. } if self.main.is_zero() => {
+                self.stones = self.stones.saturating_sub(1);
+                if self.stones == 0 {
+                    self.period = period;
+                    self.stones = stones;
+                }
+            }
+            _ => {}
+        }
+    }
+
+    fn time_left(&self, control: TimeControl) -> TimeLeft {
+        match control {
+            TimeControl::ByoYomi { period, .. } if self.main.is_zero() => TimeLeft {
+                time: period,
+                stones: self.periods,
+            },
+            TimeControl::Canadian { .. } if self.main.is_zero() => TimeLeft {
+                time: self.period,
+                stones: self.stones,
+            },
+            _ => TimeLeft {
+                time: self.main,
+                stones: 0,
+            },
+        }
+    }
+}
+
+// Часы партии: время идёт у того, чей ход, и переключается после каждого хода
+pub struct Clock {
+    control: TimeControl,
+    black: Side,
+    white: Side,
+    // чьё время идёт и с какого момента
+    running: Option<(Color, Instant)>,
+}
+
+impl Clock {
+    pub fn new(control: TimeControl) -> Clock {
+        Clock {
+            control,
+            black: Side::new(control),
+            white: Side::new(control),
+            running: None,
+        }
+    }
+
+    pub fn control(&self) -> TimeControl {
+        self.control
+    }
+
+    fn side(&mut self, color: Color) -> &mut Side {
+        match color {
+            Color::Black => &mut self.black,
+            Color::White => &mut self.white,
+        }
+    }
+
+    // Запускает время игрока color, например перед первым ходом партии
+    pub fn start(&mut self, color: Color) {
+        self.running = Some((color, Instant::now()));
+    }
+
+    // Останавливает часы без переключения, например при паузе
+    pub fn stop(&mut self) {
+        if let Some((color, since)) = self.running.take() {
+            let control = self.control;
+            self.side(color).spend(control, since.elapsed());
+        }
+    }
+
+    // Игрок color сделал ход: списываем его время и запускаем время соперника.
+    // Если часы ещё не шли, первый ход делается бесплатно.
+    pub fn press(&mut self, color: Color) {
+        if let Some((running, _)) = self.running
+            && running == color
+        {
+            self.stop();
+            let control = self.control;
+            self.side(color).moved(control);
+        }
+        self.start(color.opposite());
+    }
+
+    // Остаток времени игрока color на текущий момент, с учётом идущего хода
+    pub fn time_left(&self, color: Color) -> TimeLeft {
+        let mut side = match color {
+            Color::Black => self.black,
+            Color::White => self.white,
+        };
+        if let Some((running, since)) = self.running
+            && running == color
+        {
+            side.spend(self.control, since.elapsed());
+        }
+        side.time_left(self.control)
+    }
+
+    // Время игрока color вышло
+    pub fn flagged(&self, color: Color) -> bool {
+        let side = match color {
+            Color::Black => &self.black,
+            Color::White => &self.white,
+        };
+        side.flagged
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn secs(secs: u64) -> Duration {
+        Duration::from_secs(secs)
+    }
+
+    fn left(time: u64, stones: u32) -> TimeLeft {
+        TimeLeft {
+            time: secs(time),
+            stones,
+        }
+    }
+
+    #[test]
+    fn byo_yomi_loses_whole_periods() {
+        let control = TimeControl::ByoYomi {
+            main: secs(60),
+            period: secs(10),
+            periods: 3,
+        };
+        let mut side = Side::new(control);
+        side.spend(control, secs(30));
+        assert_eq!(side.time_left(control), left(30, 0));
+
+        // 15 секунд сверх основного времени сжигают один период
+        side.spend(control, secs(45));
+        side.moved(control);
+        assert_eq!(side.time_left(control), left(10, 2));
+
+        // ход внутри периода ничего не сжигает
+        side.spend(control, secs(9));
+        assert_eq!(side.time_left(control), left(10, 2));
+        assert!(!side.flagged);
+
+        side.spend(control, secs(25));
+        assert_eq!(side.time_left(control), left(10, 0));
+        assert!(side.flagged);
+    }
+
+    #[test]
+    fn canadian_period_resets_after_stones() {
+        let control = TimeControl::Canadian {
+            main: secs(10),
+            period: secs(60),
+            stones: 3,
+        };
+        let mut side = Side::new(control);
+        side.spend(control, secs(15));
+        side.moved(control);
+        assert_eq!(side.time_left(control), left(55, 2));
+
+        side.spend(control, secs(20));
+        side.moved(control);
+        assert_eq!(side.time_left(control), left(35, 1));
+
+        // последний ход периода начинает новый период целиком
+        side.spend(control, secs(20));
+        side.moved(control);
+        assert_eq!(side.time_left(control), left(60, 3));
+        assert!(!side.flagged);
+
+        side.spend(control, secs(61));
+        assert!(side.flagged);
+    }
+
+    #[test]
+    fn fischer_adds_increment() {
+        let control = TimeControl::Fischer {
+            main: secs(30),
+            increment: secs(5),
+        };
+        let mut side = Side::new(control);
+        side.spend(control, secs(10));
+        side.moved(control);
+        assert_eq!(side.time_left(control), left(25, 0));
+        assert!(!side.flagged);
+
+        side.spend(control, secs(26));
+        assert_eq!(side.time_left(control), left(0, 0));
+        assert!(side.flagged);
+    }
+
+    #[test]
+    fn absolute_and_unlimited() {
+        let control = TimeControl::Absolute { main: secs(30) };
+        let mut side = Side::new(control);
+        side.spend(control, secs(30));
+        assert!(!side.flagged);
+        side.spend(control, secs(1));
+        assert!(side.flagged);
+
+        let mut side = Side::new(TimeControl::Unlimited);
+        side.spend(TimeControl::Unlimited, secs(3600));
+        assert!(!side.flagged);
+    }
+
+    #[test]
+    fn first_press_is_free() {
+        let mut clock = Clock::new(TimeControl::Absolute { main: secs(30) });
+        clock.press(Color::Black);
+        assert_eq!(clock.time_left(Color::Black), left(30, 0));
+        assert!(clock.time_left(Color::White).time <= secs(30));
+        assert!(!clock.flagged(Color::White));
+    }
+}
@@ -0,0 +1,361 @@
+// Настройки приложения из файла TOML или JSON: пути KataGo, камера, распознавание.
+// Любой раздел и любое поле можно не указывать, тогда берётся значение по умолчанию.
+
+use super::board::{Color, KoRule, MAX_SIZE, ScoringRule};
+use super::katago::{self, HumanProfile};
+use super::vision;
+use clap::{Parser, Subcommand};
+use serde::{Deserialize, Serialize};
+use std::{fmt, fs, io, path::Path};
+
+#[derive(Clone, Debug, Serialize, Deserialize)]
+#[serde(default)]
+pub struct App {
+    // номер камеры для OpenCV
+    pub camera: i32,
+    pub frame_width: f64,
+    pub frame_height: f64,
+    pub board_size: usize,
+    pub komi: f32,
+    // правило ко в игре с движком: simple, positional_superko или situational_superko
+    pub ko_rule: KoRule,
+    // подсчёт после двух пасов: area или territory
+    pub scoring_rule: ScoringRule,
+}
+
+impl Default for App {
+    fn default() -> App {
+        App {
+            camera: 0,
+            frame_width: 1920.,
+            frame_height: 1080.,
+            board_size: 19,
+            komi: 7.5,
+            // как в китайских правилах и tromp-taylor
+            ko_rule: KoRule::PositionalSuperko,
+            scoring_rule: ScoringRule::Area,
+        }
+    }
+}
+
+#[derive(Clone, Debug, Default, Serialize, Deserialize)]
+#[serde(default)]
+pub struct Config {
+    pub app: App,
+    pub katago: katago::Settings,
+    pub vision: vision::Settings,
+}
+
+#[derive(Debug)]
+pub enum Error {
+    Io(io::Error),
+    // файл не разобрался как TOML или JSON
+    Parse(String),
+    // значение вне допустимых пределов
+    Invalid(String),
+}
+
+impl From<io::Error> for Error {
+    fn from(e: io::Error) -> Self {
+        Error::Io(e)
+    }
+}
+
+impl fmt::Display for Error {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Error::Io(e) => write!(f, "{e}"),
+            Error::Parse(e) => write!(f, "invalid config: {e}"),
+            Error::Invalid(e) => write!(f, "invalid config value: {e}"),
+        }
+    }
+}
+
+pub type Result<T> = std::result::Result<T, Error>;
+
+fn is_json(path: &Path) -> bool {
+    path.extension().is_some_and(|ext| ext == "json")
+}
+
+impl Config {
+    // Формат определяется по расширению: .json это JSON, всё остальное TOML
+    pub fn load(path: impl AsRef<Path>) -> Result<Config> {
+        let path = path.as_ref();
+        let text = fs::read_to_string(path)?;
+        if is_json(path) {
+            serde_json::from_str(&text).map_err(|e| Error::Parse(e.to_string()))
+        } else {
+            toml::from_str(&text).map_err(|e| Error::Parse(e.to_string()))
+        }
+    }
+
+    // Как load, но если файла нет, то настройки по умолчанию
+    pub fn load_or_default(path: impl AsRef<Path>) -> Result<Config> {
+        match Config::load(path) {
+            Err(Error::Io(e)) if e.kind() == io::ErrorKind::NotFound => Ok(Config::default()),
+            res => res,
+        }
+    }
+
+    pub fn save(&self, path: impl AsRef<Path>) -> Result<()> {
+        let path = path.as_ref();
+        let text = if is_json(path) {
+            serde_json::to_string_pretty(self).map_err(|e| Error::Parse(e.to_string()))?
+        } else {
+            toml::to_string_pretty(self).map_err(|e| Error::Parse(e.to_string()))?
+        };
+        fs::write(path, text)?;
+        Ok(())
+    }
+
+    pub fn validate(&self) -> Result<()> {
+        if !(2..=MAX_SIZE).contains(&self.app.board_size) {
+            return Err(Error::Invalid(format!(
+                "board_size must be within 2..={MAX_SIZE}"
+            )));
+        }
+        if self.app.frame_width <= 0. || self.app.frame_height <= 0. {
+            return Err(Error::Invalid(String::from(
+                "frame_width and frame_height must be positive",
+            )));
+        }
+        // пути KataGo проверяются только там, где движок нужен, см. validate_katago
+        self.vision.validate().map_err(Error::Invalid)?;
+        Ok(())
+    }
+
+    pub fn validate_katago(&self) -> Result<()> {
+        self.katago.validate().map_err(Error::Invalid)
+    }
+
+    // Значения из командной строки важнее значений из файла
+    pub fn apply(&mut self, args: &Args) {
+        if let Some(camera) = args.camera {
+            self.app.camera = camera;
+        }
+        if let Some(width) = args.width {
+            self.app.frame_width = width;
+        }
+        if let Some(height) = args.height {
+            self.app.frame_height = height;
+        }
+        if let Some(size) = args.board_size {
+            self.app.board_size = size;
+        }
+        if let Some(komi) = args.komi {
+            self.app.komi = komi;
+        }
+        // без --katago-program остаётся программа из файла
+        if let Some(dir) = &args.katago_dir {
+            let program = args
+                .katago_program
+                .clone()
+                .unwrap_or_else(|| String::from(self.katago.program()));
+            self.katago = self.katago.clone().with_program(dir, &program);
+        }
+        if let Some(model) = &args.katago_model {
+            self.katago = self.katago.clone().with_model(model);
+        }
+        if let Some(dir) = &args.dump_dir {
+            self.vision = self.vision.clone().with_dump_dir(dir);
+        }
+        if args.no_dump {
+            self.vision = self.vision.clone().with_dump_steps(false);
+        }
+    }
+}
+
+// Параметры командной строки. Общие параметры можно писать и после подкоманды.
+#[derive(Parser, Debug)]
+#[command(version, about = "Go playing robot")]
+pub struct Args {
+    #[command(subcommand)]
+    pub command: Command,
+    #[arg(
+        long,
+        global = true,
+        default_value = "robogo.toml",
+        help = "Config file, TOML or JSON"
+    )]
+    pub config: String,
+    #[arg(long, global = true, help = "Camera index")]
+    pub camera: Option<i32>,
+    #[arg(long, global = true, help = "Camera frame width")]
+    pub width: Option<f64>,
+    #[arg(long, global = true, help = "Camera frame height")]
+    pub height: Option<f64>,
+    #[arg(long, global = true)]
+    pub board_size: Option<usize>,
+    #[arg(long, global = true)]
+    pub komi: Option<f32>,
+    #[arg(
+        long,
+        global = true,
+        help = "Directory with KataGo, its configs and models"
+    )]
+    pub katago_dir: Option<String>,
+    #[arg(
+        long,
+        global = true,
+        requires = "katago_dir",
+        help = "KataGo executable inside katago-dir"
+    )]
+    pub katago_program: Option<String>,
+    #[arg(long, global = true, help = "KataGo model file inside katago-dir")]
+    pub katago_model: Option<String>,
+    #[arg(long, global = true, help = "Directory for recognition debug images")]
+    pub dump_dir: Option<String>,
+    #[arg(long, global = true, help = "Do not save recognition debug images")]
+    pub no_dump: bool,
+}
+
+#[derive(Subcommand, Debug)]
+pub enum Command {
+    #[command(about = "Recognize the board on an image")]
+    Recognize { image: String },
+    #[command(about = "Record a game from the camera to SGF")]
+    Watch {
+        #[arg(long, help = "SGF file, named by the current time by default")]
+        sgf: Option<String>,
+    },
+    #[command(about = "Play against KataGo on the real board")]
+    Play {
+        #[arg(long, default_value = "black", value_parser = parse_color, help = "Your color")]
+        color: Color,
+        #[arg(long, value_parser = parse_profile, help = "KataGo human style, e.g. rank_5k")]
+        profile: Option<HumanProfile>,
+        #[arg(long, value_parser = clap::value_parser!(u8).range(2..=9), help = "Handicap stones for black")]
+        handicap: Option<u8>,
+        #[arg(long, help = "Main time in seconds")]
+        main_time: Option<u64>,
+        #[arg(long, help = "Byo-yomi period in seconds")]
+        byo_yomi: Option<u64>,
+        #[arg(long, default_value_t = 5, help = "Byo-yomi periods")]
+        periods: u32,
+        #[arg(long, requires = "byo_yomi", help = "Stones per Canadian period")]
+        stones: Option<u32>,
+        #[arg(long, conflicts_with = "byo_yomi", help = "Fischer increment, seconds")]
+        increment: Option<u64>,
+        #[arg(long, help = "SGF file, named by the current time by default")]
+        sgf: Option<String>,
+    },
+    #[command(about = "Analyze the last position of an SGF game with KataGo")]
+    Analyze {
+        sgf: String,
+        #[arg(long, default_value_t = 10, help = "Analysis time in seconds")]
+        time: u64,
+        #[arg(long, help = "Also review every move with the KataGo analysis engine")]
+        review: bool,
+        #[arg(long, requires = "review", help = "Visits per position in the review")]
+        visits: Option<u32>,
+        #[arg(long, help = "Draw the ownership over this photo of the position")]
+        image: Option<String>,
+    },
+    #[command(about = "Calibrate recognition and save it to the config file")]
+    Calibrate,
+    #[command(about = "Recognize saved camera frames from a directory in order")]
+    Replay {
+        dir: String,
+        #[arg(long, help = "Save the recognized moves to this SGF file")]
+        sgf: Option<String>,
+    },
+}
+
+fn parse_color(s: &str) -> std::result::Result<Color, String> {
+    s.parse()
+        .map_err(|_| format!("invalid color {s}, expected black or white"))
+}
+
+fn parse_profile(s: &str) -> std::result::Result<HumanProfile, String> {
+    s.parse().map_err(|_| {
+        format!(
+            "invalid profile {s}, expected rank_<N>k, rank_<N>d, preaz_<rank> or proyear_<year>"
+        )
+    })
+}
+
+// Время в файле настроек записывается секундами, 0 значит без ограничения
+pub mod seconds {
+    use serde::{Deserialize, Deserializer, Serializer};
+    use std::time::Duration;
+
+    pub fn serialize<S: Serializer>(
+        value: &Option<Duration>,
+        serializer: S,
+    ) -> Result<S::Ok, S::Error> {
+        serializer.serialize_f64(value.map_or(0., |value| value.as_secs_f64()))
+    }
+
+    pub fn deserialize<'de, D: Deserializer<'de>>(
+        deserializer: D,
+    ) -> Result<Option<Duration>, D::Error> {
+        let secs = f64::deserialize(deserializer)?;
+        if secs <= 0. {
+            return Ok(None);
+        }
+        Duration::try_from_secs_f64(secs)
+            .map(Some)
+            .map_err(serde::de::Error::custom)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn apply(file: &str, args: &[&str]) -> Config {
+        let mut config: Config = toml::from_str(file).unwrap();
+        let args = Args::try_parse_from(["robogo"].iter().chain(args)).unwrap();
+        config.apply(&args);
+        config
+    }
+
+    #[test]
+    fn katago_dir_keeps_configured_program() {
+        let file = "[katago]\nprogram = \"katago.exe\"\n";
+        let config = apply(file, &["--katago-dir", "/opt/katago", "recognize", "a.jpg"]);
+        assert_eq!(config.katago.program(), "katago.exe");
+
+        let args = [
+            "--katago-dir",
+            "/opt/katago",
+            "--katago-program",
+            "./katago-cuda",
+            "recognize",
+            "a.jpg",
+        ];
+        let config = apply(file, &args);
+        assert_eq!(config.katago.program(), "./katago-cuda");
+    }
+
+    #[test]
+    fn cli_overrides_file() {
+        let file = "[app]\nboard_size = 19\nkomi = 7.5\n";
+        let config = apply(file, &["--board-size", "9", "recognize", "a.jpg"]);
+        assert_eq!(config.app.board_size, 9);
+        assert_eq!(config.app.komi, 7.5);
+    }
+
+    #[test]
+    fn play_handicap_range() {
+        let args = Args::try_parse_from(["robogo", "play", "--handicap", "4"]).unwrap();
+        assert!(matches!(
+            args.command,
+            Command::Play {
+                handicap: Some(4),
+                ..
+            }
+        ));
+        assert!(Args::try_parse_from(["robogo", "play", "--handicap", "1"]).is_err());
+        assert!(Args::try_parse_from(["robogo", "play", "--handicap", "10"]).is_err());
+    }
+
+    #[test]
+    fn play_time_control_options() {
+        let args = ["robogo", "play", "--byo-yomi", "300", "--stones", "25"];
+        assert!(Args::try_parse_from(args).is_ok());
+        assert!(Args::try_parse_from(["robogo", "play", "--stones", "25"]).is_err());
+        let args = ["robogo", "play", "--byo-yomi", "30", "--increment", "10"];
+        assert!(Args::try_parse_from(args).is_err());
+    }
+}
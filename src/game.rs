@@ -0,0 +1,533 @@
+use super::board::{
+    Board, Cell, Color, DeadStones, GameResult, KoRule, Move, PlayError, Position, Score,
+    ScoringRule,
+};
+use super::clock::Clock;
+use super::gtp::{self, GtpEngine};
+use super::sgf::{self, GameTree};
+
+// Ход партии вместе с камнями, которые он снял
+pub struct MoveRecord {
+    pub color: Color,
+    pub mv: Move,
+    pub captured: Vec<Position>,
+}
+
+// Партия: доска, история ходов с возможностью отмены и перехода к любому ходу
+pub struct Game {
+    size: usize,
+    komi: f32,
+    handicap: Vec<Position>,
+    // камни расстановки, которые стоят на доске до первого хода, например AB и AW из SGF
+    setup: Vec<(Color, Position)>,
+    // кто ходит первым после форы и расстановки
+    first: Color,
+    result: Option<GameResult>,
+    moves: Vec<MoveRecord>,
+    // сколько ходов из moves сейчас стоит на доске, остальные доступны для redo
+    cursor: usize,
+    // позиция после cursor ходов
+    board: Board,
+    // сколько первых ходов уже сыграно на движке, None если состояние движка неизвестно
+    synced: Option<usize>,
+    // часы партии, если играем на время
+    clock: Option<Clock>,
+}
+
+impl Game {
+    pub fn new(size: usize, komi: f32) -> Game {
+        Game::with_handicap(size, komi, Vec::new())
+    }
+
+    pub fn with_handicap(size: usize, komi: f32, handicap: Vec<Position>) -> Game {
+        let first = if handicap.is_empty() {
+            Color::Black
+        } else {
+            Color::White
+        };
+        Game::build(size, komi, handicap, Vec::new(), first)
+    }
+
+    // Партия с начальной позицией из камней setup, первым ходит first.
+    // Движку эти камни передаются ходами play.
+    pub fn with_setup(size: usize, komi: f32, setup: Vec<(Color, Position)>, first: Color) -> Game {
+        Game::build(size, komi, Vec::new(), setup, first)
+    }
+
+    // Партия из основного варианта SGF. Если расстановка AB, AW или AE встречается
+    // после первых ходов, позиция после последней расстановки становится начальной.
+    pub fn from_sgf(tree: &GameTree) -> sgf::Result<Game> {
+        let info = &tree.info;
+        let nodes = tree.main_line();
+        let last_setup = nodes.iter().rposition(|node| {
+            !node.add_black.is_empty() || !node.add_white.is_empty() || !node.clear.is_empty()
+        });
+        let (mut game, rest) = match last_setup {
+            None => {
+                let game = Game::with_handicap(info.size, info.komi, info.handicap.clone());
+                (game, &nodes[..])
+            }
+            Some(last) => {
+                let board = tree.board_at(&vec![0; last])?;
+                let setup = (0..info.size * info.size)
+                    .map(|idx| Position::new(idx % info.size, idx / info.size))
+                    .filter_map(|pos| board.stone(pos).map(|color| (color, pos)))
+                    .collect();
+                let first = match nodes[..=last].iter().rev().find_map(|node| node.mv) {
+                    Some((color, _)) => color.opposite(),
+                    None if info.handicap.is_empty() => Color::Black,
+                    None => Color::White,
+                };
+                let game = Game::with_setup(info.size, info.komi, setup, first);
+                (game, &nodes[last + 1..])
+            }
+        };
+        for (color, mv) in rest.iter().filter_map(|node| node.mv) {
+            game.record(color, mv)?;
+        }
+        Ok(game)
+    }
+
+    fn build(
+        size: usize,
+        komi: f32,
+        handicap: Vec<Position>,
+        setup: Vec<(Color, Position)>,
+        first: Color,
+    ) -> Game {
+        let mut game = Game {
+            size,
+            komi,
+            handicap,
+            setup,
+            first,
+            result: None,
+            moves: Vec::new(),
+            cursor: 0,
+            board: Board::new_with_size(size),
+            synced: None,
+            clock: None,
+        };
+        game.rebuild();
+        game
+    }
+
+    pub fn board(&self) -> &Board {
+        &self.board
+    }
+
+    pub fn size(&self) -> usize {
+        self.size
+    }
+
+    pub fn komi(&self) -> f32 {
+        self.komi
+    }
+
+    pub fn handicap(&self) -> &[Position] {
+        &self.handicap
+    }
+
+    pub fn setup(&self) -> &[(Color, Position)] {
+        &self.setup
+    }
+
+    pub fn result(&self) -> Option<GameResult> {
+        self.result
+    }
+
+    pub fn set_result(&mut self, result: GameResult) {
+        self.result = Some(result);
+    }
+
+    pub fn ko_rule(&self) -> KoRule {
+        self.board.ko_rule()
+    }
+
+    // Правило ко для следующих ходов. С суперко повтор позиции отклоняется ещё до движка.
+    pub fn set_ko_rule(&mut self, rule: KoRule) {
+        self.board.set_ko_rule(rule);
+    }
+
+    pub fn clock(&self) -> Option<&Clock> {
+        self.clock.as_ref()
+    }
+
+    // Игра на время. Часы переключаются после каждого нового хода.
+    pub fn set_clock(&mut self, clock: Clock) {
+        self.clock = Some(clock);
+        // движку нужно заново передать контроль времени
+        self.synced = None;
+    }
+
+    // Ходы до текущего, без отменённых
+    pub fn moves(&self) -> &[MoveRecord] {
+        &self.moves[..self.cursor]
+    }
+
+    pub fn move_number(&self) -> usize {
+        self.cursor
+    }
+
+    pub fn move_count(&self) -> usize {
+        self.moves.len()
+    }
+
+    pub fn next_color(&self) -> Color {
+        match self.moves().last() {
+            Some(record) => record.color.opposite(),
+            None => self.first,
+        }
+    }
+
+    // Записывает ход любого вида, например полученный от движка
+    pub fn record(&mut self, color: Color, mv: Move) -> Result<&MoveRecord, PlayError> {
+        match mv {
+            Move::Play(pos) => self.play(color, pos),
+            Move::Pass => Ok(self.pass(color)),
+            Move::Resign => Ok(self.resign(color)),
+        }
+    }
+
+    pub fn play(&mut self, color: Color, pos: Position) -> Result<&MoveRecord, PlayError> {
+        let captured = self.board.play(color, pos)?;
+        Ok(self.push(color, Move::Play(pos), captured))
+    }
+
+    pub fn pass(&mut self, color: Color) -> &MoveRecord {
+        self.board.pass(color);
+        self.push(color, Move::Pass, Vec::new())
+    }
+
+    pub fn resign(&mut self, color: Color) -> &MoveRecord {
+        self.result = Some(GameResult::Resign(color.opposite()));
+        self.push(color, Move::Resign, Vec::new())
+    }
+
+    // Новый ход после отмены отбрасывает отменённые ходы
+    fn push(&mut self, color: Color, mv: Move, captured: Vec<Position>) -> &MoveRecord {
+        // отменённые ходы ещё стоят на движке, а после truncate их уже не отменить по одному
+        if self.synced.is_some_and(|synced| synced > self.cursor) {
+            self.synced = None;
+        }
+        self.moves.truncate(self.cursor);
+        self.moves.push(MoveRecord {
+            color,
+            mv,
+            captured,
+        });
+        self.cursor += 1;
+        if let Some(clock) = &mut self.clock {
+            match mv {
+                Move::Resign => clock.stop(),
+                _ => clock.press(color),
+            }
+            if clock.flagged(color) && self.result.is_none() {
+                self.result = Some(GameResult::Time(color.opposite()));
+            }
+        }
+        &self.moves[self.cursor - 1]
+    }
+
+    pub fn undo(&mut self) -> bool {
+        if self.cursor == 0 {
+            return false;
+        }
+        self.goto(self.cursor - 1)
+    }
+
+    pub fn redo(&mut self) -> bool {
+        if self.cursor == self.moves.len() {
+            return false;
+        }
+        self.goto(self.cursor + 1)
+    }
+
+    // Переход к позиции после move_number ходов
+    pub fn goto(&mut self, move_number: usize) -> bool {
+        if move_number > self.moves.len() {
+            return false;
+        }
+        self.cursor = move_number;
+        self.rebuild();
+        true
+    }
+
+    // Позиция пересчитывается с начала, чтобы история для суперко всегда была верной.
+    // Записанные ходы уже проверены, поэтому повторяются без суперко, а правило партии
+    // включается после них: ход, сделанный до set_ko_rule, не должен ломать пересчёт.
+    fn rebuild(&mut self) {
+        let mut board = Board::new_with_size(self.size);
+        for &pos in &self.handicap {
+            board.set(pos, Cell::black_stone());
+        }
+        for &(color, pos) in &self.setup {
+            board.set(pos, Cell::from(color));
+        }
+        for record in &self.moves[..self.cursor] {
+            match record.mv {
+                Move::Play(pos) => {
+                    board
+                        .play(record.color, pos)
+                        .expect("recorded move must be legal");
+                }
+                Move::Pass => board.pass(record.color),
+                Move::Resign => {}
+            }
+        }
+        board.set_ko_rule(self.board.ko_rule());
+        self.board = board;
+    }
+
+    // Подсчёт текущей позиции с коми партии
+    pub fn score(&self, dead: &DeadStones, rule: ScoringRule) -> Score {
+        self.board.score(dead, self.komi, rule)
+    }
+
+    // Приводит состояние движка к текущей позиции: отменяет лишние ходы и доигрывает недостающие
+    pub fn sync<E: GtpEngine>(&mut self, engine: &mut E) -> gtp::Result<()> {
+        let mut synced = match self.synced {
+            Some(synced) => synced,
+            None => {
+                engine.boardsize(self.size)?;
+                engine.clear_board()?;
+                engine.komi(self.komi)?;
+                if let Some(clock) = &self.clock {
+                    engine.time_settings(clock.control())?;
+                }
+                // так движок знает, что это фора и первым ходят белые
+                if !self.handicap.is_empty() {
+                    engine.set_free_handicap(&self.handicap)?;
+                }
+                // на доске с легальной позицией такие ходы ничего не снимают
+                for &(color, pos) in &self.setup {
+                    engine.play(color, Move::Play(pos))?;
+                }
+                0
+            }
+        };
+        // пока движок не догнал партию, считаем его состояние неизвестным
+        self.synced = None;
+        while synced > self.cursor {
+            synced -= 1;
+            // сдача на движке не играется, значит и отменять нечего
+            if self.moves[synced].mv != Move::Resign {
+                engine.undo()?;
+            }
+        }
+        for record in &self.moves[synced..self.cursor] {
+            engine.play(record.color, record.mv)?;
+        }
+        self.synced = Some(self.cursor);
+        Ok(())
+    }
+
+    // Ход движка за цвет color, записанный в партию
+    pub fn genmove<E: GtpEngine>(&mut self, engine: &mut E, color: Color) -> gtp::Result<Move> {
+        self.sync(engine)?;
+        // чтобы движок распределял своё время сам
+        if let Some(clock) = &self.clock {
+            engine.time_left(color, clock.time_left(color))?;
+        }
+        let mv = match engine.genmove(color) {
+            Ok(mv) => mv,
+            Err(error) => {
+                // неизвестно, что стало с позицией на движке
+                self.synced = None;
+                return Err(error);
+            }
+        };
+        if let Err(error) = self.record(color, mv) {
+            // движок уже сыграл ход, которого нет в партии
+            self.synced = None;
+            return Err(error.into());
+        }
+        // ход уже сделан на движке, повторно его играть не нужно
+        self.synced = Some(self.cursor);
+        Ok(mv)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::gtp::{Error, Result};
+
+    // Движок, который только записывает команды, а на genmove отвечает заданным ходом
+    #[derive(Default)]
+    struct Recorder {
+        commands: Vec<String>,
+        genmove: String,
+        // genmove не успевает ответить
+        timeout: bool,
+    }
+
+    impl GtpEngine for Recorder {
+        fn send(&mut self, cmd: &str) -> Result<String> {
+            self.commands.push(String::from(cmd));
+            if cmd.starts_with("genmove") {
+                if self.timeout {
+                    return Err(Error::Timeout);
+                }
+                return Ok(self.genmove.clone());
+            }
+            Ok(String::new())
+        }
+    }
+
+    fn play(game: &mut Game, moves: &[&str]) {
+        for mv in moves {
+            let color = game.next_color();
+            game.record(color, mv.parse().unwrap()).unwrap();
+        }
+    }
+
+    // Ко на доске 5x5, чёрные только что взяли на C2
+    fn ko_game(rule: KoRule) -> Game {
+        let mut game = Game::new(5, 0.5);
+        game.set_ko_rule(rule);
+        play(
+            &mut game,
+            &["B3", "C3", "A2", "B2", "B1", "D2", "E5", "C1", "C2"],
+        );
+        game
+    }
+
+    #[test]
+    fn superko_rejects_move_and_keeps_history() {
+        let mut game = ko_game(KoRule::PositionalSuperko);
+        play(&mut game, &["pass", "pass"]);
+        let b2 = Position::from_gtp("B2").unwrap();
+        assert_eq!(game.play(Color::White, b2).err(), Some(PlayError::Superko));
+        assert_eq!(game.move_count(), 11);
+        assert_eq!(
+            game.board().stone(Position::from_gtp("C2").unwrap()),
+            Some(Color::Black)
+        );
+
+        // отмена после отклонённого хода возвращает позицию до пасов
+        assert!(game.undo());
+        assert!(game.undo());
+        assert_eq!(game.ko_rule(), KoRule::PositionalSuperko);
+        assert_eq!(game.board().ko(), Some(b2));
+        assert_eq!(game.play(Color::White, b2).err(), Some(PlayError::Ko));
+        assert!(game.redo());
+        assert!(game.redo());
+        assert_eq!(game.play(Color::White, b2).err(), Some(PlayError::Superko));
+    }
+
+    #[test]
+    fn simple_ko_allows_retake_after_passes() {
+        let mut game = ko_game(KoRule::Simple);
+        play(&mut game, &["pass", "pass", "B2"]);
+        assert_eq!(game.board().stone(Position::from_gtp("C2").unwrap()), None);
+    }
+
+    #[test]
+    fn sync_undoes_moves_taken_back() {
+        let mut game = Game::new(9, 7.5);
+        let mut engine = Recorder::default();
+        play(&mut game, &["A1", "B1", "C1"]);
+        game.sync(&mut engine).unwrap();
+        engine.commands.clear();
+
+        game.undo();
+        game.sync(&mut engine).unwrap();
+        assert_eq!(engine.commands, vec!["undo"]);
+        engine.commands.clear();
+
+        game.undo();
+        play(&mut game, &["D1"]);
+        game.sync(&mut engine).unwrap();
+        // движок не знает, что сыграно после отмены, поэтому партия передаётся заново
+        assert_eq!(
+            engine.commands,
+            vec![
+                "boardsize 9",
+                "clear_board",
+                "komi 7.5",
+                "play Black A1",
+                "play White D1"
+            ]
+        );
+    }
+
+    #[test]
+    fn illegal_genmove_forces_resync() {
+        let mut game = Game::new(9, 7.5);
+        let mut engine = Recorder {
+            genmove: String::from("A1"),
+            ..Recorder::default()
+        };
+        play(&mut game, &["A1"]);
+        assert!(game.genmove(&mut engine, Color::White).is_err());
+        assert_eq!(game.move_count(), 1);
+
+        engine.commands.clear();
+        game.sync(&mut engine).unwrap();
+        assert_eq!(engine.commands[0], "boardsize 9");
+    }
+
+    #[test]
+    fn failed_genmove_forces_resync() {
+        let mut game = Game::new(9, 7.5);
+        let mut engine = Recorder {
+            timeout: true,
+            ..Recorder::default()
+        };
+        play(&mut game, &["A1"]);
+        assert!(matches!(
+            game.genmove(&mut engine, Color::White),
+            Err(Error::Timeout)
+        ));
+        assert_eq!(game.move_count(), 1);
+
+        engine.commands.clear();
+        game.sync(&mut engine).unwrap();
+        assert_eq!(engine.commands[0], "boardsize 9");
+    }
+
+    #[test]
+    fn sgf_setup_without_handicap() {
+        let tree = sgf::parse("(;GM[1]SZ[9]KM[6.5]AB[cc][gg]AW[cg];W[ee];B[dd])").unwrap();
+        let game = Game::from_sgf(&tree).unwrap();
+        assert_eq!(game.setup().len(), 3);
+        assert_eq!(game.move_count(), 2);
+        assert_eq!(game.next_color(), Color::White);
+        let stone = |s| game.board().stone(Position::from_gtp(s).unwrap());
+        assert_eq!(stone("C7"), Some(Color::Black));
+        assert_eq!(stone("C3"), Some(Color::White));
+        assert_eq!(stone("E5"), Some(Color::White));
+
+        let mut game = game;
+        let mut engine = Recorder::default();
+        game.sync(&mut engine).unwrap();
+        assert_eq!(
+            engine.commands,
+            vec![
+                "boardsize 9",
+                "clear_board",
+                "komi 6.5",
+                "play White C3",
+                "play Black G3",
+                "play Black C7",
+                "play White E5",
+                "play Black D6"
+            ]
+        );
+    }
+
+    #[test]
+    fn sgf_setup_after_moves_starts_from_that_position() {
+        // после двух ходов чёрный камень на ee убран, а белый добавлен
+        let tree = sgf::parse("(;SZ[9];B[ee];W[cc];AE[ee]AW[gg];B[dd])").unwrap();
+        let game = Game::from_sgf(&tree).unwrap();
+        assert_eq!(game.move_count(), 1);
+        assert_eq!(game.next_color(), Color::White);
+        let stone = |s| game.board().stone(Position::from_gtp(s).unwrap());
+        assert_eq!(stone("E5"), None);
+        assert_eq!(stone("C7"), Some(Color::White));
+        assert_eq!(stone("G3"), Some(Color::White));
+        assert_eq!(stone("D6"), Some(Color::Black));
+    }
+}
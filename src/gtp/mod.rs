@@ -0,0 +1,366 @@
+use super::board::{
+    Color, GameResult, Move, ParseColorError, ParsePositionError, ParseResultError, PlayError,
+    Position,
+};
+use super::clock::{TimeControl, TimeLeft};
+use chrono::Local;
+use std::fmt::{self, Display};
+use std::str::FromStr;
+use std::{io, num::ParseIntError, process::ExitStatus};
+
+mod process;
+mod reader;
+
+pub use process::{GtpProcess, Settings};
+
+#[derive(Debug)]
+pub enum Error {
+    Io(io::Error),
+    InvalidTextProtocol,
+    ParseIntError,
+    ParsePositionError,
+    ParseResultError,
+    InvalidJson(String),
+    // движок сделал ход, невозможный по нашим правилам
+    IllegalMove(PlayError),
+    UnknownError(String),
+    // движок не ответил за отведённое время
+    Timeout,
+    // процесс движка завершился
+    EngineExited(ExitStatus),
+    // позиция на движке разошлась с нашей
+    Desync(Desync),
+}
+
+// Чем позиция на движке отличается от нашей доски
+#[derive(Debug, PartialEq)]
+pub enum Desync {
+    // размер доски на движке (ширина, высота) и у нас
+    Size {
+        engine: (usize, usize),
+        local: usize,
+    },
+    // точки, где камни разные: (точка, на движке, у нас)
+    Stones(Vec<(Position, Option<Color>, Option<Color>)>),
+    // число снятых камней цвета color
+    Captured {
+        color: Color,
+        engine: u32,
+        local: u32,
+    },
+}
+
+impl From<io::Error> for Error {
+    fn from(e: io::Error) -> Error {
+        Error::Io(e)
+    }
+}
+
+impl From<ParseIntError> for Error {
+    fn from(_: ParseIntError) -> Self {
+        Error::ParseIntError
+    }
+}
+
+impl From<ParsePositionError> for Error {
+    fn from(_: ParsePositionError) -> Self {
+        Error::ParsePositionError
+    }
+}
+
+impl From<ParseColorError> for Error {
+    fn from(_: ParseColorError) -> Self {
+        Error::InvalidTextProtocol
+    }
+}
+
+impl From<ParseResultError> for Error {
+    fn from(_: ParseResultError) -> Self {
+        Error::ParseResultError
+    }
+}
+
+impl From<PlayError> for Error {
+    fn from(e: PlayError) -> Self {
+        Error::IllegalMove(e)
+    }
+}
+
+impl Display for Error {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Error::Io(e) => write!(f, "{e}"),
+            Error::InvalidTextProtocol => write!(f, "unexpected response from the engine"),
+            Error::ParseIntError => write!(f, "invalid number in the engine response"),
+            Error::ParsePositionError => write!(f, "invalid point in the engine response"),
+            Error::ParseResultError => write!(f, "invalid game result in the engine response"),
+            Error::InvalidJson(e) => write!(f, "invalid JSON from the engine: {e}"),
+            Error::IllegalMove(e) => write!(f, "engine played an illegal move: {e}"),
+            Error::UnknownError(text) => write!(f, "{}", text.trim()),
+            Error::Timeout => write!(f, "engine did not respond in time"),
+            Error::EngineExited(status) => write!(f, "engine exited with {status}"),
+            Error::Desync(desync) => write!(f, "engine position differs: {desync}"),
+        }
+    }
+}
+
+impl Display for Desync {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Desync::Size {
+                engine: (width, height),
+                local,
+            } => write!(
+                f,
+                "board {width}x{height} on the engine, {local}x{local} here"
+            ),
+            Desync::Stones(stones) => {
+                let points: Vec<String> = stones.iter().map(|(pos, _, _)| pos.to_gtp()).collect();
+                write!(f, "stones differ at {}", points.join(" "))
+            }
+            Desync::Captured {
+                color,
+                engine,
+                local,
+            } => write!(
+                f,
+                "{engine} {color} stones captured on the engine, {local} here"
+            ),
+        }
+    }
+}
+
+pub type Result<T> = std::result::Result<T, Error>;
+
+// Статус камней для final_status_list
+#[derive(PartialEq, Eq, Clone, Copy, Debug)]
+pub enum StoneStatus {
+    Alive,
+    Dead,
+    Seki,
+}
+
+impl Display for StoneStatus {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            StoneStatus::Alive => write!(f, "alive"),
+            StoneStatus::Dead => write!(f, "dead"),
+            StoneStatus::Seki => write!(f, "seki"),
+        }
+    }
+}
+
+// Список точек через пробелы или переводы строк, как в ответах на команды форы
+fn positions(answer: &str) -> Result<Vec<Position>> {
+    let mut res = Vec::new();
+    for word in answer.split_whitespace() {
+        res.push(Position::from_str(word)?);
+    }
+    Ok(res)
+}
+
+pub fn timestamp() -> String {
+    let now = Local::now();
+    format!("{}", now.format("%F %T"))
+}
+
+// Стандартные команды GTP, общие для KataGo, GNU Go, Leela Zero и других движков.
+// Реализации достаточно уметь отправить команду, остальное построено поверх send.
+pub trait GtpEngine {
+    // Ответ движка без "= ", ответ "?" превращается в ошибку
+    fn send(&mut self, cmd: &str) -> Result<String>;
+
+    fn play(&mut self, color: Color, mv: Move) -> Result<()> {
+        // сдача не ход на доске, движку её не передаём
+        if mv == Move::Resign {
+            return Ok(());
+        }
+        self.send(&format!("play {color} {mv}"))?;
+        Ok(())
+    }
+
+    fn genmove(&mut self, color: Color) -> Result<Move> {
+        let answer = self.send(&format!("genmove {color}"))?;
+        let mv = Move::from_str(&answer)?;
+        Ok(mv)
+    }
+
+    // Доска в текстовом виде, формат у каждого движка свой
+    fn showboard(&mut self) -> Result<String> {
+        self.send("showboard")
+    }
+
+    fn undo(&mut self) -> Result<()> {
+        self.send("undo")?;
+        Ok(())
+    }
+
+    fn clear_board(&mut self) -> Result<()> {
+        self.send("clear_board")?;
+        Ok(())
+    }
+
+    fn komi(&mut self, komi: f32) -> Result<()> {
+        self.send(&format!("komi {komi}"))?;
+        Ok(())
+    }
+
+    fn boardsize(&mut self, size: usize) -> Result<()> {
+        self.send(&format!("boardsize {size}"))?;
+        Ok(())
+    }
+
+    // Контроль времени. Стандартный time_settings знает абсолютное и канадское время,
+    // бёё-ёми передаётся через kgs-time_settings. Фишера в GTP нет, его добавляют расширения.
+    fn time_settings(&mut self, control: TimeControl) -> Result<()> {
+        let cmd = match control {
+            // по GTP ненулевой период без ходов значит отсутствие ограничения
+            TimeControl::Unlimited => String::from("time_settings 0 1 0"),
+            TimeControl::Absolute { main } => format!("time_settings {} 0 0", main.as_secs()),
+            TimeControl::Canadian {
+                main,
+                period,
+                stones,
+            } => format!(
+                "time_settings {} {} {stones}",
+                main.as_secs(),
+                period.as_secs()
+            ),
+            TimeControl::ByoYomi {
+                main,
+                period,
+                periods,
+            } => format!(
+                "kgs-time_settings byoyomi {} {} {periods}",
+                main.as_secs(),
+                period.as_secs()
+            ),
+            TimeControl::Fischer { .. } => {
+                return Err(Error::UnknownError(String::from(
+                    "fischer time is not supported",
+                )));
+            }
+        };
+        self.send(&cmd)?;
+        Ok(())
+    }
+
+    // Сколько времени осталось у color, отправляется перед каждым genmove
+    fn time_left(&mut self, color: Color, left: TimeLeft) -> Result<()> {
+        self.send(&format!(
+            "time_left {color} {} {}",
+            left.time.as_secs(),
+            left.stones
+        ))?;
+        Ok(())
+    }
+
+    // Фора на стандартных пунктах, движок сам выбирает точки и возвращает их
+    fn fixed_handicap(&mut self, count: usize) -> Result<Vec<Position>> {
+        let answer = self.send(&format!("fixed_handicap {count}"))?;
+        positions(&answer)
+    }
+
+    // Свободная фора, точки для камней выбирает движок
+    fn place_free_handicap(&mut self, count: usize) -> Result<Vec<Position>> {
+        let answer = self.send(&format!("place_free_handicap {count}"))?;
+        positions(&answer)
+    }
+
+    // Свободная фора на заданных нами точках
+    fn set_free_handicap(&mut self, stones: &[Position]) -> Result<()> {
+        let stones: Vec<String> = stones.iter().map(|pos| pos.to_gtp()).collect();
+        self.send(&format!("set_free_handicap {}", stones.join(" ")))?;
+        Ok(())
+    }
+
+    // Подсчёт результата движком, в той же записи что и RE в SGF
+    fn final_score(&mut self) -> Result<GameResult> {
+        let answer = self.send("final_score")?;
+        let result = GameResult::from_str(&answer)?;
+        Ok(result)
+    }
+
+    // Камни с заданным статусом по мнению движка, например мёртвые для подсчёта
+    fn final_status_list(&mut self, status: StoneStatus) -> Result<Vec<Position>> {
+        let answer = self.send(&format!("final_status_list {status}"))?;
+        positions(&answer)
+    }
+
+    // Все команды, которые понимает движок
+    fn list_commands(&mut self) -> Result<Vec<String>> {
+        let answer = self.send("list_commands")?;
+        Ok(answer.lines().map(String::from).collect())
+    }
+
+    fn known_command(&mut self, cmd: &str) -> Result<bool> {
+        let answer = self.send(&format!("known_command {cmd}"))?;
+        Ok(answer.trim() == "true")
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    // Движок, который записывает команды и на всё отвечает одной заданной строкой
+    #[derive(Default)]
+    struct Recorder {
+        commands: Vec<String>,
+        answer: String,
+    }
+
+    impl GtpEngine for Recorder {
+        fn send(&mut self, cmd: &str) -> Result<String> {
+            self.commands.push(String::from(cmd));
+            Ok(self.answer.clone())
+        }
+    }
+
+    fn pos(s: &str) -> Position {
+        Position::from_gtp(s).unwrap()
+    }
+
+    #[test]
+    fn fixed_handicap_parses_vertices() {
+        let mut engine = Recorder {
+            answer: String::from("D4 Q16 D16"),
+            ..Recorder::default()
+        };
+        let stones = engine.fixed_handicap(3).unwrap();
+        assert_eq!(engine.commands, vec!["fixed_handicap 3"]);
+        assert_eq!(stones, vec![pos("D4"), pos("Q16"), pos("D16")]);
+    }
+
+    #[test]
+    fn place_free_handicap_parses_vertices() {
+        let mut engine = Recorder {
+            answer: String::from("C3\nG7\n"),
+            ..Recorder::default()
+        };
+        let stones = engine.place_free_handicap(2).unwrap();
+        assert_eq!(engine.commands, vec!["place_free_handicap 2"]);
+        assert_eq!(stones, vec![pos("C3"), pos("G7")]);
+    }
+
+    #[test]
+    fn bad_handicap_vertex_is_an_error() {
+        let mut engine = Recorder {
+            answer: String::from("D4 Z99"),
+            ..Recorder::default()
+        };
+        assert!(matches!(
+            engine.fixed_handicap(2),
+            Err(Error::ParsePositionError)
+        ));
+    }
+
+    #[test]
+    fn set_free_handicap_sends_vertices() {
+        let mut engine = Recorder::default();
+        engine
+            .set_free_handicap(&[pos("D4"), pos("Q16"), pos("K10")])
+            .unwrap();
+        assert_eq!(engine.commands, vec!["set_free_handicap D4 Q16 K10"]);
+    }
+}
@@ -0,0 +1,477 @@
+use super::reader::{self, Log, Output};
+use super::{Color, Error, GtpEngine, Move, Position, Result};
+use std::str::FromStr;
+use std::{
+    fs::File,
+    io::{self, Write},
+    process::{Child, ChildStdin, Command, Stdio},
+    sync::{
+        Arc, Mutex,
+        mpsc::{Receiver, RecvTimeoutError, TryRecvError},
+    },
+    time::Duration,
+};
+
+// Как запускать движок: программа с аргументами и поведение при сбоях
+#[derive(Clone)]
+pub struct Settings {
+    dir: String,
+    program: String,
+    args: Vec<String>,
+    log_filename: Option<String>,
+    // сколько ждать ответа на команду, None значит ждать сколько угодно
+    command_timeout: Option<Duration>,
+    // перезапускать ли упавший движок с повтором партии
+    restart_on_crash: bool,
+    // начало строки в stderr, после которой движок готов, None если готов сразу
+    ready_line: Option<String>,
+}
+
+impl Settings {
+    pub fn new(dir: &str, program: &str, args: &[&str]) -> Settings {
+        Settings {
+            dir: String::from(dir),
+            program: String::from(program),
+            args: args.iter().map(|arg| String::from(*arg)).collect(),
+            log_filename: None,
+            command_timeout: Some(Duration::from_secs(60)),
+            restart_on_crash: true,
+            ready_line: None,
+        }
+    }
+
+    pub fn log_to(mut self, filename: &str) -> Settings {
+        self.log_filename = Some(String::from(filename));
+        self
+    }
+
+    pub fn command_timeout(mut self, timeout: Option<Duration>) -> Settings {
+        self.command_timeout = timeout;
+        self
+    }
+
+    pub fn restart_on_crash(mut self, restart: bool) -> Settings {
+        self.restart_on_crash = restart;
+        self
+    }
+
+    pub fn ready_line(mut self, line: &str) -> Settings {
+        self.ready_line = Some(String::from(line));
+        self
+    }
+}
+
+// Запущенный процесс движка и потоки, читающие его вывод
+struct Process {
+    child: Child,
+    stdin: ChildStdin,
+    output: Receiver<Output>,
+    ready: Receiver<()>,
+}
+
+fn spawn(settings: &Settings, log: &Log) -> Result<Process> {
+    let mut child = Command::new(&settings.program)
+        .current_dir(&settings.dir)
+        .args(&settings.args)
+        .stdin(Stdio::piped())
+        .stdout(Stdio::piped())
+        .stderr(Stdio::piped())
+        .spawn()?;
+    let not_available = || io::Error::new(io::ErrorKind::BrokenPipe, "GTP engine pipe not aviable");
+    let stdin = child.stdin.take().ok_or_else(not_available)?;
+    let stdout = child.stdout.take().ok_or_else(not_available)?;
+    let stderr = child.stderr.take().ok_or_else(not_available)?;
+    // stdout и stderr читаются постоянно в своих потоках, так ни один буфер не переполнится
+    Ok(Process {
+        child,
+        stdin,
+        output: reader::spawn_stdout(stdout, log.clone()),
+        ready: reader::spawn_stderr(stderr, log.clone(), settings.ready_line.clone()),
+    })
+}
+
+// Команды, после которых позиция на движке уже другая
+fn changes_position(cmd: &str) -> bool {
+    matches!(
+        cmd.split_whitespace().next(),
+        Some(
+            "play"
+                | "genmove"
+                | "undo"
+                | "clear_board"
+                | "boardsize"
+                | "fixed_handicap"
+                | "place_free_handicap"
+                | "set_free_handicap"
+        )
+    )
+}
+
+// Ответ, который ещё не дочитан до конца
+struct Partial {
+    id: Option<u32>,
+    success: bool,
+    text: String,
+}
+
+// Любой движок, говорящий по GTP через stdin и stdout
+pub struct GtpProcess {
+    settings: Settings,
+    process: Child,
+    stdin: ChildStdin,
+    output: Receiver<Output>,
+    ready: Receiver<()>,
+    log: Log,
+    partial: Option<Partial>,
+    // номер следующей команды
+    next_id: u32,
+    // номер команды, ответ на которую мы ждём, ответы на остальные отбрасываются
+    expected: Option<u32>,
+    timeout: Option<Duration>,
+    // состояние партии на движке, которое повторяется после перезапуска
+    board_size: usize,
+    komi: Option<f32>,
+    // последняя команда с контролем времени
+    time_settings: Option<String>,
+    // команды расширений протокола, которые владелец просил повторять, по ключу
+    replayed: Vec<(String, String)>,
+    handicap: Vec<Position>,
+    moves: Vec<(Color, Move)>,
+}
+
+impl GtpProcess {
+    pub fn new(settings: Settings) -> Result<GtpProcess> {
+        let log = match &settings.log_filename {
+            Some(filename) if !filename.is_empty() => {
+                Some(Arc::new(Mutex::new(File::create(filename)?)))
+            }
+            _ => None,
+        };
+        let process = spawn(&settings, &log)?;
+        Ok(GtpProcess {
+            timeout: settings.command_timeout,
+            settings,
+            process: process.child,
+            stdin: process.stdin,
+            output: process.output,
+            ready: process.ready,
+            log,
+            partial: None,
+            next_id: 1,
+            expected: None,
+            board_size: 19,
+            komi: None,
+            time_settings: None,
+            replayed: Vec::new(),
+            handicap: Vec::new(),
+            moves: Vec::new(),
+        })
+    }
+
+    // Запускает движок заново и повторяет на нём размер доски, коми, контроль времени,
+    // команды из replay_after_restart, фору и все ходы партии
+    pub fn restart(&mut self) -> Result<()> {
+        let _ = self.process.kill();
+        let _ = self.process.wait();
+        let process = spawn(&self.settings, &self.log)?;
+        self.process = process.child;
+        self.stdin = process.stdin;
+        self.output = process.output;
+        self.ready = process.ready;
+        self.partial = None;
+        self.expected = None;
+        self.wait_ready()?;
+
+        let timeout = self.timeout;
+        self.send_once(&format!("boardsize {}", self.board_size), timeout)?;
+        if let Some(komi) = self.komi {
+            self.send_once(&format!("komi {komi}"), timeout)?;
+        }
+        if let Some(cmd) = self.time_settings.clone() {
+            self.send_once(&cmd, timeout)?;
+        }
+        for (_, cmd) in self.replayed.clone() {
+            self.send_once(&cmd, timeout)?;
+        }
+        if !self.handicap.is_empty() {
+            let stones: Vec<String> = self.handicap.iter().map(|pos| pos.to_gtp()).collect();
+            self.send_once(&format!("set_free_handicap {}", stones.join(" ")), timeout)?;
+        }
+        for (color, mv) in self.moves.clone() {
+            self.send_once(&format!("play {color} {mv}"), timeout)?;
+        }
+        Ok(())
+    }
+
+    fn has_exited(&mut self) -> bool {
+        matches!(self.process.try_wait(), Ok(Some(_)))
+    }
+
+    // Ждёт строку готовности в stderr, если она задана в настройках
+    pub fn wait_ready(&mut self) -> Result<()> {
+        if self.settings.ready_line.is_some() && self.ready.recv().is_err() {
+            return Err(self.exited());
+        }
+        Ok(())
+    }
+
+    // Таймаут по умолчанию для всех команд
+    pub fn set_timeout(&mut self, timeout: Option<Duration>) {
+        self.timeout = timeout;
+    }
+
+    // Ошибка с кодом завершения движка, вызывается когда его вывод закрылся
+    fn exited(&mut self) -> Error {
+        match self.process.wait() {
+            Ok(status) => Error::EngineExited(status),
+            Err(e) => Error::Io(e),
+        }
+    }
+
+    fn next_output(&mut self, timeout: Option<Duration>) -> Result<Output> {
+        let output = match timeout {
+            Some(timeout) => self.output.recv_timeout(timeout),
+            None => self
+                .output
+                .recv()
+                .map_err(|_| RecvTimeoutError::Disconnected),
+        };
+        match output {
+            Ok(output) => Ok(output),
+            Err(RecvTimeoutError::Timeout) => Err(Error::Timeout),
+            Err(RecvTimeoutError::Disconnected) => Err(self.exited()),
+        }
+    }
+
+    fn write_command(&mut self, cmd: &str) -> Result<()> {
+        writeln!(self.stdin, "{}", cmd)?;
+        self.stdin.flush()?;
+        reader::write_log(&self.log, "CMD", cmd);
+        Ok(())
+    }
+
+    // Собирает ответ из частей, возвращает его, когда ответ закончился
+    fn collect(&mut self, output: Output) -> Option<Result<String>> {
+        match output {
+            Output::Begin { id, success } => {
+                self.partial = Some(Partial {
+                    id,
+                    success,
+                    text: String::new(),
+                })
+            }
+            Output::Line(text) => {
+                if let Some(partial) = &mut self.partial {
+                    partial.text.push_str(&text);
+                    partial.text.push('\n');
+                }
+            }
+            Output::End => {
+                let partial = self.partial.take()?;
+                // опоздавший ответ на отменённую команду
+                if partial.id.is_none() || partial.id != self.expected {
+                    return None;
+                }
+                self.expected = None;
+                if partial.success {
+                    return Some(Ok(partial.text));
+                }
+                return Some(Err(Error::UnknownError(partial.text)));
+            }
+        }
+        None
+    }
+
+    // Отправляет команду, не дожидаясь ответа, и возвращает её номер. Ответ забирается через
+    // try_response или wait_response, а пока движок думает, можно разбирать кадры с камеры.
+    // Команды, отправленные так, не запоминаются для повтора после перезапуска.
+    pub fn request(&mut self, cmd: &str) -> Result<u32> {
+        let id = self.next_id;
+        self.next_id += 1;
+        self.write_command(&format!("{id} {cmd}"))?;
+        self.expected = Some(id);
+        Ok(id)
+    }
+
+    // Перестаём ждать ответ на последнюю команду, когда он придёт, он будет отброшен
+    pub fn cancel(&mut self) {
+        self.expected = None;
+    }
+
+    // Ответ на отправленную команду, если он уже пришёл целиком
+    pub fn try_response(&mut self) -> Result<Option<String>> {
+        loop {
+            let output = match self.output.try_recv() {
+                Ok(output) => output,
+                Err(TryRecvError::Empty) => return Ok(None),
+                Err(TryRecvError::Disconnected) => return Err(self.exited()),
+            };
+            if let Some(response) = self.collect(output) {
+                return response.map(Some);
+            }
+        }
+    }
+
+    pub fn wait_response(&mut self) -> Result<String> {
+        self.wait_response_for(self.timeout)
+    }
+
+    // После Error::Timeout ответ на команду всё ещё может прийти, он будет отброшен
+    pub fn wait_response_for(&mut self, timeout: Option<Duration>) -> Result<String> {
+        loop {
+            let output = self.next_output(timeout)?;
+            if let Some(response) = self.collect(output) {
+                return response;
+            }
+        }
+    }
+
+    fn send_once(&mut self, cmd: &str, timeout: Option<Duration>) -> Result<String> {
+        self.request(cmd)?;
+        self.wait_response_for(timeout)
+    }
+
+    // То же, что send, но со своим таймаутом, например для долгого genmove.
+    // Если движок упал, он перезапускается и команда повторяется один раз.
+    // Если не дождались ответа на команду, меняющую позицию, неизвестно, выполнил ли её движок,
+    // поэтому он перезапускается с известной позицией, а вызывающий получает Error::Timeout.
+    pub fn send_with_timeout(&mut self, cmd: &str, timeout: Option<Duration>) -> Result<String> {
+        let response = match self.send_once(cmd, timeout) {
+            Err(error) if self.settings.restart_on_crash && self.has_exited() => {
+                reader::write_log(&self.log, "RESTART", &error.to_string());
+                self.restart()?;
+                self.send_once(cmd, timeout)
+            }
+            Err(Error::Timeout) if changes_position(cmd) => {
+                reader::write_log(&self.log, "RESTART", &Error::Timeout.to_string());
+                self.restart()?;
+                Err(Error::Timeout)
+            }
+            res => res,
+        }?;
+        self.remember(cmd, &response);
+        Ok(response)
+    }
+
+    // Команда не из стандартного GTP, которую нужно повторить после перезапуска.
+    // Команда с тем же ключом заменяет прежнюю.
+    pub fn replay_after_restart(&mut self, key: &str, cmd: &str) {
+        self.stop_replaying(key);
+        self.replayed.push((String::from(key), String::from(cmd)));
+    }
+
+    pub fn stop_replaying(&mut self, key: &str) {
+        self.replayed.retain(|(name, _)| name != key);
+    }
+
+    // Запоминает команды, меняющие позицию, чтобы повторить их после перезапуска
+    fn remember(&mut self, cmd: &str, response: &str) {
+        let words: Vec<&str> = cmd.split_whitespace().collect();
+        match words.as_slice() {
+            ["play", color, mv] => {
+                if let (Ok(color), Ok(mv)) = (Color::from_str(color), Move::from_str(mv)) {
+                    self.moves.push((color, mv));
+                }
+            }
+            ["genmove", color] => {
+                if let (Ok(color), Ok(mv)) = (Color::from_str(color), Move::from_str(response))
+                    && mv != Move::Resign
+                {
+                    self.moves.push((color, mv));
+                }
+            }
+            ["undo"] => {
+                self.moves.pop();
+            }
+            ["clear_board"] => {
+                self.moves.clear();
+                self.handicap.clear();
+            }
+            ["komi", komi] => self.komi = komi.parse::<f32>().ok(),
+            ["time_settings" | "kgs-time_settings", ..] => {
+                self.time_settings = Some(String::from(cmd));
+            }
+            ["boardsize", size] => {
+                if let Ok(size) = size.parse::<usize>() {
+                    self.board_size = size;
+                    self.moves.clear();
+                    self.handicap.clear();
+                }
+            }
+            // движок сам выбрал точки форы и прислал их в ответе
+            ["fixed_handicap" | "place_free_handicap", _] => {
+                self.handicap = super::positions(response).unwrap_or_default();
+            }
+            ["set_free_handicap", stones @ ..] => {
+                self.handicap = stones.iter().filter_map(|pos| pos.parse().ok()).collect();
+            }
+            _ => {}
+        }
+    }
+
+    // Команда, ответ на которую идёт строками без конца, как у kata-analyze или lz-analyze.
+    // on_line вызывается на каждую строку, поток останавливается, когда он вернёт false.
+    pub fn stream<F>(&mut self, cmd: &str, mut on_line: F) -> Result<()>
+    where
+        F: FnMut(&str) -> Result<bool>,
+    {
+        let id = self.request(cmd)?;
+        // ответ читается здесь, а не через collect
+        self.expected = None;
+
+        let mut current = false;
+        let mut success = true;
+        let mut failure = String::new();
+        let mut error = None;
+        let mut stopped = false;
+        loop {
+            match self.next_output(self.timeout)? {
+                Output::Begin {
+                    id: response_id,
+                    success: ok,
+                } => {
+                    current = response_id == Some(id);
+                    success = ok;
+                }
+                // строки опоздавших ответов на другие команды
+                Output::Line(_) | Output::End if !current => {}
+                Output::Line(text) if !success => failure.push_str(&text),
+                Output::Line(_) if stopped => {}
+                Output::Line(text) => {
+                    let proceed = on_line(&text).unwrap_or_else(|e| {
+                        error = Some(e);
+                        false
+                    });
+                    if !proceed {
+                        // любая строка на входе останавливает поток
+                        self.write_command("")?;
+                        stopped = true;
+                    }
+                }
+                // поток кончается пустой строкой после остановки
+                Output::End => break,
+            }
+        }
+        if !success {
+            return Err(Error::UnknownError(failure));
+        }
+        match error {
+            Some(error) => Err(error),
+            None => Ok(()),
+        }
+    }
+}
+
+impl GtpEngine for GtpProcess {
+    fn send(&mut self, cmd: &str) -> Result<String> {
+        self.send_with_timeout(cmd, self.timeout)
+    }
+}
+
+// Без этого процесс движка остаётся зомби до выхода программы
+impl Drop for GtpProcess {
+    fn drop(&mut self) {
+        let _ = self.process.kill();
+        let _ = self.process.wait();
+    }
+}
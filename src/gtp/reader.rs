@@ -0,0 +1,211 @@
+use super::timestamp;
+use std::{
+    fs::File,
+    io::{BufRead, BufReader, Read, Write},
+    sync::{
+        Arc, Mutex,
+        mpsc::{Receiver, Sender, channel},
+    },
+    thread,
+};
+
+// Лог общий для потока команд и потоков чтения
+pub type Log = Option<Arc<Mutex<File>>>;
+
+pub fn write_log(log: &Log, kind: &str, text: &str) {
+    if let Some(file) = log
+        && let Ok(mut file) = file.lock()
+    {
+        let _ = writeln!(file, "[{}] {}: {}", timestamp(), kind, text);
+    }
+}
+
+// Ответ GTP, разобранный на части по мере чтения, чтобы длинные ответы
+// вроде kata-analyze можно было обрабатывать не дожидаясь их конца
+#[derive(Debug, PartialEq)]
+pub enum Output {
+    // начало ответа "=id" или "?id"
+    Begin { id: Option<u32>, success: bool },
+    Line(String),
+    // пустая строка, которой кончается каждый ответ
+    End,
+}
+
+fn parse_header(line: &str) -> Option<(Option<u32>, bool, &str)> {
+    let success = match line.as_bytes().first() {
+        Some(b'=') => true,
+        Some(b'?') => false,
+        _ => return None,
+    };
+    let rest = &line[1..];
+    let digits = rest.bytes().take_while(|ch| ch.is_ascii_digit()).count();
+    let id = rest[..digits].parse::<u32>().ok();
+    Some((id, success, rest[digits..].trim_start()))
+}
+
+// Поток чтения stdout: режет вывод на ответы и отдаёт их по каналу
+pub fn spawn_stdout(stdout: impl Read + Send + 'static, log: Log) -> Receiver<Output> {
+    let (sender, receiver) = channel();
+    thread::spawn(move || {
+        let mut in_response = false;
+        for line in BufReader::new(stdout).lines().map_while(|line| line.ok()) {
+            write_log(&log, "READ", &line);
+            if !send_line(&sender, &mut in_response, line) {
+                break;
+            }
+        }
+    });
+    receiver
+}
+
+fn send_line(sender: &Sender<Output>, in_response: &mut bool, line: String) -> bool {
+    if !*in_response {
+        // между ответами могут быть лишние пустые строки
+        let Some((id, success, rest)) = parse_header(&line) else {
+            return true;
+        };
+        *in_response = true;
+        if sender.send(Output::Begin { id, success }).is_err() {
+            return false;
+        }
+        if rest.is_empty() {
+            return true;
+        }
+        return sender.send(Output::Line(String::from(rest))).is_ok();
+    }
+    if line.is_empty() {
+        *in_response = false;
+        return sender.send(Output::End).is_ok();
+    }
+    sender.send(Output::Line(line)).is_ok()
+}
+
+// Поток чтения stderr: всё пишет в лог и сообщает, когда движок готов принимать команды
+pub fn spawn_stderr(
+    stderr: impl Read + Send + 'static,
+    log: Log,
+    ready_line: Option<String>,
+) -> Receiver<()> {
+    let (sender, receiver) = channel();
+    thread::spawn(move || {
+        for line in BufReader::new(stderr).lines().map_while(|line| line.ok()) {
+            write_log(&log, "STDERR", &line);
+            if let Some(ready_line) = &ready_line
+                && line.starts_with(ready_line.as_str())
+            {
+                let _ = sender.send(());
+            }
+        }
+    });
+    receiver
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::io::Cursor;
+    use std::time::Duration;
+
+    // Всё, что поток чтения stdout выдаст на текст text
+    fn read_all(text: &str) -> Vec<Output> {
+        let receiver = spawn_stdout(Cursor::new(text.as_bytes().to_vec()), None);
+        receiver.iter().collect()
+    }
+
+    fn line(text: &str) -> Output {
+        Output::Line(String::from(text))
+    }
+
+    #[test]
+    fn headers() {
+        assert_eq!(parse_header("="), Some((None, true, "")));
+        assert_eq!(parse_header("= D4"), Some((None, true, "D4")));
+        assert_eq!(parse_header("=12 D4"), Some((Some(12), true, "D4")));
+        assert_eq!(
+            parse_header("?3 unknown command"),
+            Some((Some(3), false, "unknown command"))
+        );
+        assert_eq!(
+            parse_header("? illegal move"),
+            Some((None, false, "illegal move"))
+        );
+        assert_eq!(parse_header(""), None);
+        assert_eq!(parse_header("info move D4"), None);
+    }
+
+    #[test]
+    fn single_line_responses() {
+        assert_eq!(
+            read_all("=1 D4\n\n?2 illegal move\n\n=3\n\n"),
+            vec![
+                Output::Begin {
+                    id: Some(1),
+                    success: true
+                },
+                line("D4"),
+                Output::End,
+                Output::Begin {
+                    id: Some(2),
+                    success: false
+                },
+                line("illegal move"),
+                Output::End,
+                Output::Begin {
+                    id: Some(3),
+                    success: true
+                },
+                Output::End,
+            ]
+        );
+    }
+
+    #[test]
+    fn multi_line_response() {
+        // как showboard: первая строка после заголовка, остальные до пустой строки
+        assert_eq!(
+            read_all("= MoveNum: 0\n   A B\n 2 . .\n 1 . X\n\n"),
+            vec![
+                Output::Begin {
+                    id: None,
+                    success: true
+                },
+                line("MoveNum: 0"),
+                line("   A B"),
+                line(" 2 . ."),
+                line(" 1 . X"),
+                Output::End,
+            ]
+        );
+    }
+
+    #[test]
+    fn stray_lines_between_responses() {
+        assert_eq!(
+            read_all("\nGTP ready\n\n=5 ok\n\n\n"),
+            vec![
+                Output::Begin {
+                    id: Some(5),
+                    success: true
+                },
+                line("ok"),
+                Output::End,
+            ]
+        );
+    }
+
+    #[test]
+    fn stderr_signals_ready_line_only() {
+        let text = "Loading model\nGTP ready, beginning main protocol loop\nWarning\n";
+        let ready = spawn_stderr(
+            Cursor::new(text.as_bytes().to_vec()),
+            None,
+            Some(String::from("GTP ready")),
+        );
+        assert_eq!(ready.recv_timeout(Duration::from_secs(5)), Ok(()));
+        // после конца stderr канал закрывается, других сигналов нет
+        assert!(ready.recv().is_err());
+
+        let ready = spawn_stderr(Cursor::new(b"Loading model\n".to_vec()), None, None);
+        assert!(ready.recv().is_err());
+    }
+}
@@ -0,0 +1,399 @@
+use super::{Color, Error, Move, MoveInfo, Result, Settings};
+use crate::board::Position;
+use crate::gtp::timestamp;
+use serde::{Deserialize, Serialize};
+use std::str::FromStr;
+use std::sync::mpsc::{self, Receiver};
+use std::{
+    fs::File,
+    io::{self, BufRead, BufReader, Write},
+    process::{Child, ChildStdin, Command, Stdio},
+    thread,
+};
+
+// Запрос на анализ одной партии. KataGo отвечает отдельно на каждый ход из analyze_turns,
+// а если они не заданы, то только на позицию после последнего хода.
+pub struct Query {
+    pub id: String,
+    pub moves: Vec<(Color, Move)>,
+    pub initial_stones: Vec<(Color, Position)>,
+    pub rules: String,
+    pub komi: f32,
+    pub board_size: usize,
+    pub analyze_turns: Option<Vec<usize>>,
+    pub max_visits: Option<u32>,
+    pub include_ownership: bool,
+}
+
+impl Query {
+    pub fn new(id: &str, moves: Vec<(Color, Move)>) -> Query {
+        Query {
+            id: String::from(id),
+            moves,
+            initial_stones: Vec::new(),
+            rules: String::from("chinese"),
+            komi: 7.5,
+            board_size: 19,
+            analyze_turns: None,
+            max_visits: None,
+            include_ownership: false,
+        }
+    }
+
+    // Сколько ответов придёт на этот запрос
+    fn responses_count(&self) -> usize {
+        match &self.analyze_turns {
+            Some(turns) => turns.len(),
+            None => 1,
+        }
+    }
+}
+
+// Результат анализа одной позиции. Оценки даны с той стороны,
+// что указана в reportAnalysisWinratesAs конфига анализа.
+pub struct Analysis {
+    pub id: String,
+    pub turn_number: usize,
+    pub winrate: f64,
+    pub score_lead: f64,
+    pub visits: u32,
+    pub moves: Vec<MoveInfo>,
+    // владение каждой клеткой от -1 до 1, построчно сверху вниз
+    pub ownership: Option<Vec<f64>>,
+}
+
+fn color_letter(color: Color) -> String {
+    match color {
+        Color::Black => String::from("B"),
+        Color::White => String::from("W"),
+    }
+}
+
+#[derive(Serialize)]
+#[serde(rename_all = "camelCase")]
+struct RawQuery<'a> {
+    id: &'a str,
+    moves: Vec<[String; 2]>,
+    initial_stones: Vec<[String; 2]>,
+    rules: &'a str,
+    komi: f32,
+    board_x_size: usize,
+    board_y_size: usize,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    analyze_turns: Option<&'a [usize]>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    max_visits: Option<u32>,
+    include_ownership: bool,
+}
+
+impl<'a> RawQuery<'a> {
+    fn from(query: &'a Query) -> RawQuery<'a> {
+        RawQuery {
+            id: &query.id,
+            moves: query
+                .moves
+                .iter()
+                .filter(|(_, mv)| *mv != Move::Resign)
+                .map(|(color, mv)| [color_letter(*color), mv.to_string()])
+                .collect(),
+            initial_stones: query
+                .initial_stones
+                .iter()
+                .map(|(color, pos)| [color_letter(*color), pos.to_gtp()])
+                .collect(),
+            rules: &query.rules,
+            komi: query.komi,
+            board_x_size: query.board_size,
+            board_y_size: query.board_size,
+            analyze_turns: query.analyze_turns.as_deref(),
+            max_visits: query.max_visits,
+            include_ownership: query.include_ownership,
+        }
+    }
+}
+
+#[derive(Deserialize)]
+#[serde(rename_all = "camelCase")]
+struct RawMoveInfo {
+    #[serde(rename = "move")]
+    mv: String,
+    visits: u32,
+    winrate: f64,
+    score_lead: f64,
+    prior: f64,
+    order: u32,
+    pv: Vec<String>,
+}
+
+#[derive(Deserialize)]
+#[serde(rename_all = "camelCase")]
+struct RawRootInfo {
+    winrate: f64,
+    score_lead: f64,
+    visits: u32,
+}
+
+#[derive(Deserialize)]
+#[serde(rename_all = "camelCase")]
+struct RawResponse {
+    #[serde(default)]
+    id: String,
+    error: Option<String>,
+    warning: Option<String>,
+    #[serde(default)]
+    turn_number: usize,
+    #[serde(default)]
+    move_infos: Vec<RawMoveInfo>,
+    root_info: Option<RawRootInfo>,
+    ownership: Option<Vec<f64>>,
+}
+
+fn parse_move_info(raw: RawMoveInfo) -> Result<MoveInfo> {
+    let mut pv = Vec::with_capacity(raw.pv.len());
+    for mv in &raw.pv {
+        pv.push(Move::from_str(mv)?);
+    }
+    Ok(MoveInfo {
+        mv: Move::from_str(&raw.mv)?,
+        visits: raw.visits,
+        winrate: raw.winrate,
+        score_lead: raw.score_lead,
+        prior: raw.prior,
+        order: raw.order,
+        pv,
+    })
+}
+
+// Ответ вместе с id запроса, на который он пришёл
+struct Response {
+    id: String,
+    result: Result<Analysis>,
+}
+
+// Разбирает строку ответа, предупреждения пропускаются.
+// У нечитаемой строки id неизвестен и остаётся пустым.
+fn parse_response(line: &str) -> Option<Response> {
+    let raw: RawResponse = match serde_json::from_str(line) {
+        Ok(raw) => raw,
+        Err(e) => {
+            return Some(Response {
+                id: String::new(),
+                result: Err(Error::InvalidJson(e.to_string())),
+            });
+        }
+    };
+    if raw.warning.is_some() && raw.error.is_none() {
+        return None;
+    }
+    let id = raw.id.clone();
+    Some(Response {
+        id,
+        result: parse_analysis(raw, line),
+    })
+}
+
+fn parse_analysis(raw: RawResponse, line: &str) -> Result<Analysis> {
+    if let Some(error) = raw.error {
+        return Err(Error::UnknownError(error));
+    }
+    let root = raw
+        .root_info
+        .ok_or_else(|| Error::InvalidJson(String::from(line)))?;
+    let mut moves = Vec::with_capacity(raw.move_infos.len());
+    for info in raw.move_infos {
+        moves.push(parse_move_info(info)?);
+    }
+    Ok(Analysis {
+        id: raw.id,
+        turn_number: raw.turn_number,
+        winrate: root.winrate,
+        score_lead: root.score_lead,
+        visits: root.visits,
+        moves,
+        ownership: raw.ownership,
+    })
+}
+
+// Движок `katago analysis`: принимает запросы в JSON и отвечает на них по мере готовности,
+// поэтому много позиций можно анализировать параллельно, не трогая партию в GTP движке
+pub struct AnalysisEngine {
+    process: Child,
+    stdin: ChildStdin,
+    responses: Receiver<Response>,
+    log: Option<File>,
+}
+
+impl AnalysisEngine {
+    pub fn new(settings: Settings) -> Result<AnalysisEngine> {
+        let mut process = Command::new(&settings.program)
+            .current_dir(settings.dir)
+            .arg("analysis")
+            .arg("-config")
+            .arg(settings.analysis_config)
+            .arg("-model")
+            .arg(settings.model)
+            .stdin(Stdio::piped())
+            .stdout(Stdio::piped())
+            .stderr(Stdio::piped())
+            .spawn()?;
+        let not_available = || io::Error::new(io::ErrorKind::BrokenPipe, "Katago pipe not aviable");
+        let stdin = process.stdin.take().ok_or_else(not_available)?;
+        let stdout = process.stdout.take().ok_or_else(not_available)?;
+        let stderr = process.stderr.take().ok_or_else(not_available)?;
+
+        let mut log = if settings.dump_to_filename && !settings.analysis_log_filename.is_empty() {
+            Some(File::create(settings.analysis_log_filename)?)
+        } else {
+            None
+        };
+
+        // stderr нужно вычитывать постоянно, иначе переполнится буфер и движок встанет
+        let mut stderr_log = match &log {
+            Some(file) => Some(file.try_clone()?),
+            None => None,
+        };
+        thread::spawn(move || {
+            for line in BufReader::new(stderr).lines().map_while(|line| line.ok()) {
+                if let Some(log) = &mut stderr_log {
+                    let _ = writeln!(log, "[{}] STDERR: {}", timestamp(), line);
+                }
+            }
+        });
+
+        let (sender, responses) = mpsc::channel();
+        let mut stdout_log = match &mut log {
+            Some(file) => Some(file.try_clone()?),
+            None => None,
+        };
+        thread::spawn(move || {
+            for line in BufReader::new(stdout).lines().map_while(|line| line.ok()) {
+                if let Some(log) = &mut stdout_log {
+                    let _ = writeln!(log, "[{}] READ: {}", timestamp(), line);
+                }
+                if let Some(response) = parse_response(&line)
+                    && sender.send(response).is_err()
+                {
+                    break;
+                }
+            }
+        });
+
+        Ok(AnalysisEngine {
+            process,
+            stdin,
+            responses,
+            log,
+        })
+    }
+
+    // Отправляет запрос, не дожидаясь ответа
+    pub fn query(&mut self, query: &Query) -> Result<()> {
+        let json = serde_json::to_string(&RawQuery::from(query))
+            .map_err(|e| Error::InvalidJson(e.to_string()))?;
+        writeln!(self.stdin, "{}", json)?;
+        self.stdin.flush()?;
+        if let Some(log) = &mut self.log {
+            writeln!(log, "[{}] QUERY: {}", timestamp(), json)?;
+        }
+        Ok(())
+    }
+
+    fn next_response(&mut self) -> Result<Response> {
+        self.responses.recv().map_err(|_| {
+            Error::Io(io::Error::new(
+                io::ErrorKind::BrokenPipe,
+                "Katago analysis stopped",
+            ))
+        })
+    }
+
+    // Следующий готовый ответ на любой из отправленных запросов
+    pub fn recv(&mut self) -> Result<Analysis> {
+        self.next_response()?.result
+    }
+
+    // Отправляет все запросы сразу и ждёт все ответы,
+    // результат упорядочен как запросы, а внутри запроса по номеру хода.
+    // Даже при ошибке в одном запросе ответы на остальные вычитываются,
+    // чтобы они не достались следующему вызову.
+    pub fn analyze(&mut self, queries: &[Query]) -> Result<Vec<Analysis>> {
+        let mut remaining = Vec::with_capacity(queries.len());
+        for query in queries {
+            self.query(query)?;
+            remaining.push(query.responses_count());
+        }
+        let mut res = Vec::new();
+        let mut error = None;
+        while remaining.iter().any(|&count| count > 0) {
+            let response = self.next_response()?;
+            let Some(idx) = queries.iter().position(|query| query.id == response.id) else {
+                // непонятно, на какой запрос был ответ, значит неизвестно и сколько их ещё ждать
+                if response.id.is_empty() {
+                    response.result?;
+                }
+                // ответ на запрос из прошлого вызова, который уже завершился ошибкой
+                continue;
+            };
+            match response.result {
+                Ok(analysis) => {
+                    remaining[idx] = remaining[idx].saturating_sub(1);
+                    res.push(analysis);
+                }
+                // на ошибочный запрос KataGo отвечает один раз, других ответов на него не будет
+                Err(e) => {
+                    remaining[idx] = 0;
+                    error.get_or_insert(e);
+                }
+            }
+        }
+        if let Some(e) = error {
+            return Err(e);
+        }
+        let order = |analysis: &Analysis| {
+            let idx = queries.iter().position(|query| query.id == analysis.id);
+            (idx, analysis.turn_number)
+        };
+        res.sort_by_key(order);
+        Ok(res)
+    }
+}
+
+impl Drop for AnalysisEngine {
+    fn drop(&mut self) {
+        let _ = self.process.kill();
+        let _ = self.process.wait();
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn parses_analysis() {
+        let line = r#"{"id":"q1","turnNumber":3,"rootInfo":{"winrate":0.6,"scoreLead":2.5,"visits":100},"moveInfos":[{"move":"D4","visits":60,"winrate":0.61,"scoreLead":2.7,"prior":0.3,"order":0,"pv":["D4","Q16"]}],"ownership":[0.5,-0.5]}"#;
+        let response = parse_response(line).unwrap();
+        assert_eq!(response.id, "q1");
+        let analysis = response.result.unwrap();
+        assert_eq!(analysis.turn_number, 3);
+        assert_eq!(analysis.visits, 100);
+        assert_eq!(analysis.moves[0].pv.len(), 2);
+        assert_eq!(analysis.ownership, Some(vec![0.5, -0.5]));
+    }
+
+    #[test]
+    fn error_keeps_query_id() {
+        let response = parse_response(r#"{"id":"q2","error":"Illegal move","field":"moves"}"#);
+        let response = response.unwrap();
+        assert_eq!(response.id, "q2");
+        assert!(matches!(response.result, Err(Error::UnknownError(_))));
+    }
+
+    #[test]
+    fn skips_warnings_and_reports_garbage() {
+        assert!(parse_response(r#"{"id":"q3","warning":"Unused field","field":"x"}"#).is_none());
+        let response = parse_response("not json").unwrap();
+        assert!(response.id.is_empty());
+        assert!(matches!(response.result, Err(Error::InvalidJson(_))));
+    }
+}
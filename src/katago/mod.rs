@@ -1,215 +1,355 @@
-use super::board::{self, Board, Color, ParsePositionError};
-use chrono::Local;
-use std::str::FromStr;
-use std::{
-    fs::File,
-    io::{self, BufRead, BufReader, Write},
-    num::ParseIntError,
-    process::{Command, Stdio},
-};
+use super::board::{Board, Cell, Color, Grid, Move, Position};
+use super::clock::TimeControl;
+use super::gtp::{self, Desync, GtpEngine, GtpProcess};
+use serde::{Deserialize, Serialize};
+use std::path::Path;
+use std::time::{Duration, Instant};
 
+mod analysis;
 mod parse;
+mod profile;
 
+pub use super::gtp::{Error, Result};
+pub use analysis::{Analysis, AnalysisEngine, Query};
+pub use profile::{HumanProfile, ParseProfileError, Rank};
+
+// Поля, которых нет в файле настроек, берутся из Settings::default()
+#[derive(Clone, Debug, Serialize, Deserialize)]
+#[serde(default)]
 pub struct Settings {
     dir: String,
+    program: String,
     config: String,
+    analysis_config: String,
     model: String,
     human_model: String,
     log_filename: String,
+    analysis_log_filename: String,
     dump_to_filename: bool,
+    // передаются в gtp::Settings как есть
+    #[serde(with = "crate::config::seconds")]
+    command_timeout: Option<Duration>,
+    restart_on_crash: bool,
+    // аргументы после стандартных, в файле настроек не хранятся
+    #[serde(skip)]
+    extra_args: Vec<String>,
 }
 
-impl Settings {
-    pub fn default() -> Settings {
+impl Default for Settings {
+    fn default() -> Settings {
         Settings {
             dir: String::from("./katago"),
+            program: String::from("./katago"),
             config: String::from("gtp_human5k_example.cfg"),
+            analysis_config: String::from("analysis_example.cfg"),
             model: String::from("kata1-b28c512nbt-s8536703232-d4684449769.bin.gz"),
             human_model: String::from("b18c384nbt-humanv0.bin"),
             log_filename: String::from("./vision_dump/katago.log"),
+            analysis_log_filename: String::from("./vision_dump/katago_analysis.log"),
             dump_to_filename: true,
+            command_timeout: Some(Duration::from_secs(60)),
+            restart_on_crash: true,
+            extra_args: Vec::new(),
         }
     }
 }
 
-#[derive(Debug)]
-pub enum Error {
-    Io(io::Error),
-    InvalidTextProtocol,
-    ParseIntError,
-    ParsePositionError,
-    UnknownError(String),
-}
+impl Settings {
+    pub fn program(&self) -> &str {
+        &self.program
+    }
 
-impl From<io::Error> for Error {
-    fn from(e: io::Error) -> Error {
-        Error::Io(e)
+    // Другой исполняемый файл вместо ./katago, например fake_katago для тестов
+    pub fn with_program(mut self, dir: &str, program: &str) -> Settings {
+        self.dir = String::from(dir);
+        self.program = String::from(program);
+        self
     }
-}
 
-impl From<ParseIntError> for Error {
-    fn from(_: ParseIntError) -> Self {
-        Error::ParseIntError
+    // Например -moves для fake_katago
+    pub fn with_args(mut self, args: &[&str]) -> Settings {
+        self.extra_args = args.iter().map(|arg| String::from(*arg)).collect();
+        self
     }
-}
 
-impl From<ParsePositionError> for Error {
-    fn from(_: ParsePositionError) -> Self {
-        Error::ParsePositionError
+    pub fn with_model(mut self, model: &str) -> Settings {
+        self.model = String::from(model);
+        self
     }
-}
 
-pub type Result<T> = std::result::Result<T, Error>;
+    // Каталог KataGo и файлы, которые в нём должны быть
+    pub fn validate(&self) -> std::result::Result<(), String> {
+        let dir = Path::new(&self.dir);
+        if !dir.is_dir() {
+            return Err(format!("katago dir {} not found", self.dir));
+        }
+        let files = [
+            &self.program,
+            &self.config,
+            &self.analysis_config,
+            &self.model,
+            &self.human_model,
+        ];
+        for file in files {
+            if !file.is_empty() && !dir.join(file).exists() {
+                return Err(format!("{} not found in {}", file, self.dir));
+            }
+        }
+        Ok(())
+    }
+}
 
+// Позиция на движке по выводу showboard
 pub struct State {
-    pub board: Board,
+    pub stones: Grid<Option<Color>>,
     pub move_num: u32,
     pub next_move: Color,
     pub black_captured: u32,
     pub white_captured: u32,
 }
 
-pub struct Katago {
-    process: std::process::Child,
-    log: Option<File>,
-    board_size: usize,
+impl State {
+    // Позиция как Board, если доска на движке квадратная
+    pub fn board(&self) -> Option<Board> {
+        if self.stones.width() != self.stones.height() {
+            return None;
+        }
+        let mut board = Board::new_with_size(self.stones.width());
+        for (pos, stone) in self.stones.iter() {
+            if let Some(color) = stone {
+                board.set(pos, Cell::from(*color));
+            }
+        }
+        Some(board)
+    }
+
+    // Сверяет позицию на движке с нашей доской
+    pub fn check(&self, board: &Board) -> std::result::Result<(), Desync> {
+        let (width, height) = (self.stones.width(), self.stones.height());
+        if width != board.size() || height != board.size() {
+            return Err(Desync::Size {
+                engine: (width, height),
+                local: board.size(),
+            });
+        }
+        let stones: Vec<_> = self
+            .stones
+            .iter()
+            .map(|(pos, &engine)| (pos, engine, board.stone(pos)))
+            .filter(|(_, engine, local)| engine != local)
+            .collect();
+        if !stones.is_empty() {
+            return Err(Desync::Stones(stones));
+        }
+        for (color, engine) in [
+            (Color::Black, self.black_captured),
+            (Color::White, self.white_captured),
+        ] {
+            let local = board.captured(color);
+            if engine != local {
+                return Err(Desync::Captured {
+                    color,
+                    engine,
+                    local,
+                });
+            }
+        }
+        Ok(())
+    }
+}
+
+// Оценка одного хода-кандидата
+pub struct MoveInfo {
+    pub mv: Move,
+    pub visits: u32,
+    pub winrate: f64,
+    pub score_lead: f64,
+    pub prior: f64,
+    // место хода в списке кандидатов, 0 это лучший
+    pub order: u32,
+    // главный вариант, начиная с самого хода
+    pub pv: Vec<Move>,
 }
 
-fn timestamp() -> String {
-    let now = Local::now();
-    format!("{}", now.format("%F %T"))
+// KataGo в режиме GTP: стандартные команды через GtpEngine и расширения kata-*
+pub struct Katago {
+    process: GtpProcess,
 }
 
 impl Katago {
     pub fn new(settings: Settings) -> Result<Katago> {
-        let process = Command::new("./katago")
-            .current_dir(settings.dir)
-            .arg("gtp")
-            .arg("-config")
-            .arg(settings.config)
-            .arg("-model")
-            .arg(settings.model)
-            .arg("-human-model")
-            .arg(settings.human_model)
-            .stdin(Stdio::piped())
-            .stdout(Stdio::piped())
-            .stderr(Stdio::piped())
-            .spawn()?;
-        let log_file = if settings.dump_to_filename && !settings.log_filename.is_empty() {
-            Some(File::create(settings.log_filename)?)
-        } else {
-            None
-        };
+        let mut args = vec![
+            "gtp",
+            "-config",
+            &settings.config,
+            "-model",
+            &settings.model,
+            "-human-model",
+            &settings.human_model,
+        ];
+        args.extend(settings.extra_args.iter().map(String::as_str));
+        let mut gtp_settings = gtp::Settings::new(&settings.dir, &settings.program, &args)
+            .command_timeout(settings.command_timeout)
+            .restart_on_crash(settings.restart_on_crash)
+            .ready_line("GTP ready");
+        if settings.dump_to_filename {
+            gtp_settings = gtp_settings.log_to(&settings.log_filename);
+        }
         Ok(Katago {
-            process: process,
-            log: log_file,
-            board_size: 19,
+            process: GtpProcess::new(gtp_settings)?,
         })
     }
 
     pub fn wait_gtp_ready(&mut self) -> Result<()> {
-        let stdout = self.process.stderr.as_mut().ok_or_else(|| {
-            io::Error::new(io::ErrorKind::BrokenPipe, "Katago stdout not aviable")
-        })?;
-        let reader = BufReader::new(stdout);
-        for line in reader.lines() {
-            let line = line?;
-            if let Some(log) = &mut self.log {
-                writeln!(log, "[{}] READ: {}", timestamp(), line)?;
-            }
-            //ждём строку с GTP ready
-            if line.starts_with("GTP ready") {
-                break;
-            }
-        }
-        Ok(())
+        //ждём строку с GTP ready
+        self.process.wait_ready()
     }
 
-    fn send(&mut self, cmd: &str) -> Result<String> {
-        let stdin =
-            self.process.stdin.as_mut().ok_or_else(|| {
-                io::Error::new(io::ErrorKind::BrokenPipe, "Katago stdin not aviable")
-            })?;
+    // Запускает движок заново, см. GtpProcess::restart. Профиль human-модели и контроль
+    // времени по Фишеру тоже повторяются.
+    pub fn restart(&mut self) -> Result<()> {
+        self.process.restart()
+    }
 
-        writeln!(stdin, "{}", cmd)?;
-        stdin.flush()?;
+    pub fn set_timeout(&mut self, timeout: Option<Duration>) {
+        self.process.set_timeout(timeout);
+    }
 
-        if let Some(log) = &mut self.log {
-            writeln!(log, "[{}] CMD: {}", timestamp(), cmd)?;
-        }
+    pub fn request(&mut self, cmd: &str) -> Result<u32> {
+        self.process.request(cmd)
+    }
 
-        let stdout = self.process.stdout.as_mut().ok_or_else(|| {
-            io::Error::new(io::ErrorKind::BrokenPipe, "Katago stdout not aviable")
-        })?;
-        let reader = BufReader::new(stdout);
-        let mut response = String::new();
-        for line in reader.lines() {
-            let line = line?;
-            if let Some(log) = &mut self.log {
-                writeln!(log, "[{}] READ: {}", timestamp(), line)?;
-            }
-            //команды кончаются пустой строкой
-            if line.is_empty() {
-                break;
-            }
-            response.push_str(&line);
-            response.push('\n');
-        }
-        Ok(response)
+    pub fn cancel(&mut self) {
+        self.process.cancel();
     }
 
-    pub fn get_current_state(&mut self) -> Result<State> {
-        let answer = self.send("showboard")?;
-        if answer.starts_with("?") {
-            return Err(Error::UnknownError(answer));
-        }
+    pub fn try_response(&mut self) -> Result<Option<String>> {
+        self.process.try_response()
+    }
 
-        let lines: Vec<&str> = answer.as_str().lines().collect();
-        if lines.len() < self.board_size + 6 {
-            return Err(Error::InvalidTextProtocol);
-        }
+    pub fn wait_response(&mut self) -> Result<String> {
+        self.process.wait_response()
+    }
 
-        let mut board = Board::new_with_size(self.board_size);
-        for line_number in 0..self.board_size {
-            let y = self.board_size - line_number - 1;
-            parse::board_line(lines[2 + line_number], self.board_size, |x, color| {
-                board.set(board::Position::new(x, y), board::Cell::from(color));
-            })?
-        }
+    pub fn wait_response_for(&mut self, timeout: Option<Duration>) -> Result<String> {
+        self.process.wait_response_for(timeout)
+    }
+
+    pub fn send_with_timeout(&mut self, cmd: &str, timeout: Option<Duration>) -> Result<String> {
+        self.process.send_with_timeout(cmd, timeout)
+    }
 
-        Ok(State {
-            move_num: parse::move_num(lines[0])?,
-            board: board,
-            next_move: parse::next_move(lines[self.board_size + 2])?,
-            black_captured: parse::black_captured(lines[self.board_size + 4])?,
-            white_captured: parse::white_captured(lines[self.board_size + 5])?,
+    // Живой анализ позиции для цвета color: kata-analyze присылает оценки ходов раз в interval.
+    // callback вызывается на каждое обновление, анализ останавливается, когда он вернёт false.
+    pub fn analyze<F>(&mut self, color: Color, interval: Duration, mut callback: F) -> Result<()>
+    where
+        F: FnMut(&[MoveInfo]) -> bool,
+    {
+        let centiseconds = (interval.as_millis() / 10).max(1);
+        let cmd = format!("kata-analyze {color} {centiseconds}");
+        self.process.stream(&cmd, |line| {
+            if !line.starts_with("info") {
+                return Ok(true);
+            }
+            let infos = parse::analyze_line(line)?;
+            Ok(callback(&infos))
         })
     }
 
-    pub fn play(&mut self, color: Color, pos: board::Position) -> Result<()> {
-        let cmd = format!("play {color} {pos}");
-        let answer = self.send(&cmd)?;
-        if answer.starts_with("?") {
-            return Err(Error::UnknownError(answer));
+    // Оценка владения территорией: анализирует позицию duration и возвращает последнюю оценку.
+    // Значения от -1 до 1, положительные за чёрных, отрицательные за белых.
+    // KataGo считает владение с точки зрения color, если reportAnalysisWinratesAs не задан.
+    pub fn ownership(&mut self, color: Color, duration: Duration) -> Result<Grid<f32>> {
+        let start = Instant::now();
+        let mut ownership = None;
+        let cmd = format!("kata-analyze {color} 20 ownership true");
+        self.process.stream(&cmd, |line| {
+            if !line.starts_with("info") {
+                return Ok(true);
+            }
+            ownership = Some(parse::ownership(line)?);
+            Ok(start.elapsed() < duration)
+        })?;
+        let mut ownership = ownership.ok_or(Error::InvalidTextProtocol)?;
+        if color == Color::White {
+            let points: Vec<_> = ownership
+                .iter()
+                .map(|(pos, &value)| (pos, -value))
+                .collect();
+            for (pos, value) in points {
+                ownership.set(pos, value);
+            }
         }
+        Ok(ownership)
+    }
+
+    // Сила и стиль, которым подражает human-модель, вместо humanSLProfile из конфига
+    pub fn set_human_profile(&mut self, profile: HumanProfile) -> Result<()> {
+        let cmd = format!("kata-set-param humanSLProfile {profile}");
+        self.send(&cmd)?;
+        self.process.replay_after_restart("humanSLProfile", &cmd);
         Ok(())
     }
 
-    pub fn genmove_for(&mut self, color: Color) -> Result<board::Position> {
-        let cmd = format!("genmove {color}");
-        let answer = self.send(&cmd)?;
-        if answer.starts_with("?") {
-            return Err(Error::UnknownError(answer));
+    pub fn get_current_state(&mut self) -> Result<State> {
+        let answer = self.showboard()?;
+        parse::showboard(&answer)
+    }
+
+    // Позиция на движке, если она совпадает с board, иначе Error::Desync
+    pub fn check_sync(&mut self, board: &Board) -> Result<State> {
+        let state = self.get_current_state()?;
+        state.check(board).map_err(Error::Desync)?;
+        Ok(state)
+    }
+}
+
+impl GtpEngine for Katago {
+    fn send(&mut self, cmd: &str) -> Result<String> {
+        self.process.send(cmd)
+    }
+
+    // KataGo понимает Фишера через своё расширение kata-time_settings
+    fn time_settings(&mut self, control: TimeControl) -> Result<()> {
+        match control {
+            TimeControl::Fischer { main, increment } => {
+                let cmd = format!(
+                    "kata-time_settings fischer {} {}",
+                    main.as_secs_f32(),
+                    increment.as_secs_f32()
+                );
+                self.send(&cmd)?;
+                self.process.replay_after_restart("time_settings", &cmd);
+                Ok(())
+            }
+            _ => {
+                self.process.time_settings(control)?;
+                // стандартный time_settings GtpProcess повторяет сам
+                self.process.stop_replaying("time_settings");
+                Ok(())
+            }
         }
-        let pos_str = answer.get(2..).ok_or_else(|| Error::InvalidTextProtocol)?;
-        let position = board::Position::from_str(pos_str)?;
-        Ok(position)
     }
 }
 
 impl std::fmt::Display for State {
     fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
-        write!(f, "{}", self.board)?;
+        for y in (0..self.stones.height()).rev() {
+            write!(f, "{:>2}| ", y + 1)?;
+            for x in 0..self.stones.width() {
+                let cell = match self.stones.get(Position::new(x, y)) {
+                    Some(Some(color)) => Cell::from(*color),
+                    _ => Cell::empty(),
+                };
+                write!(f, "{} ", cell)?;
+            }
+            writeln!(f)?;
+        }
+        write!(f, "    ")?;
+        for x in 0..self.stones.width() {
+            write!(f, "{} ", Position::new(x, 0).column_letter())?;
+        }
+        writeln!(f)?;
         writeln!(f, "move number: {}", self.move_num)?;
         writeln!(f, "next move: {}", self.next_move)?;
         writeln!(f, "black stones captured: {}", self.black_captured)?;
@@ -217,3 +357,88 @@ impl std::fmt::Display for State {
         Ok(())
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn pos(s: &str) -> Position {
+        Position::from_gtp(s).unwrap()
+    }
+
+    fn state(width: usize, height: usize, stones: &[(&str, Color)]) -> State {
+        let mut grid = Grid::new(width, height, None);
+        for (at, color) in stones {
+            grid.set(pos(at), Some(*color));
+        }
+        State {
+            stones: grid,
+            move_num: 0,
+            next_move: Color::Black,
+            black_captured: 0,
+            white_captured: 0,
+        }
+    }
+
+    fn board(stones: &[(&str, Color)]) -> Board {
+        let mut board = Board::new_with_size(9);
+        for (at, color) in stones {
+            board.set(pos(at), Cell::from(*color));
+        }
+        board
+    }
+
+    #[test]
+    fn check_in_sync() {
+        let stones = [("C3", Color::Black), ("D4", Color::White)];
+        assert_eq!(state(9, 9, &stones).check(&board(&stones)), Ok(()));
+    }
+
+    #[test]
+    fn check_size() {
+        assert_eq!(
+            state(9, 7, &[]).check(&board(&[])),
+            Err(Desync::Size {
+                engine: (9, 7),
+                local: 9
+            })
+        );
+        assert!(matches!(
+            state(13, 13, &[]).check(&board(&[])),
+            Err(Desync::Size { .. })
+        ));
+    }
+
+    #[test]
+    fn check_stones() {
+        let engine = state(9, 9, &[("C3", Color::Black), ("D4", Color::White)]);
+        let local = board(&[("C3", Color::White), ("E5", Color::Black)]);
+        let mut stones = match engine.check(&local) {
+            Err(Desync::Stones(stones)) => stones,
+            _ => panic!("expected Stones"),
+        };
+        stones.sort_by_key(|(at, _, _)| at.to_gtp());
+        assert_eq!(
+            stones,
+            vec![
+                (pos("C3"), Some(Color::Black), Some(Color::White)),
+                (pos("D4"), Some(Color::White), None),
+                (pos("E5"), None, Some(Color::Black)),
+            ]
+        );
+    }
+
+    #[test]
+    fn check_captured() {
+        let mut engine = state(9, 9, &[]);
+        engine.white_captured = 2;
+        assert_eq!(
+            engine.check(&board(&[])),
+            Err(Desync::Captured {
+                color: Color::White,
+                engine: 2,
+                local: 0
+            })
+        );
+    }
+}
@@ -1,39 +1,20 @@
-use super::Color;
-use super::{Error, Result};
+use super::{Color, Error, Move, MoveInfo, Result, State};
+use crate::board::{Grid, MAX_SIZE, Position};
+use std::str::FromStr;
 
-pub fn move_num(line: &str) -> Result<u32> {
-    if line.starts_with("= MoveNum: ") {
+fn move_num(line: &str) -> Result<u32> {
+    if line.starts_with("MoveNum: ") {
         let words: Vec<&str> = line.split_whitespace().collect();
-        if words.len() < 3 {
+        if words.len() < 2 {
             return Err(Error::InvalidTextProtocol);
         }
-        let count = words[2].parse::<u32>()?;
+        let count = words[1].parse::<u32>()?;
         return Ok(count);
     }
     Err(Error::InvalidTextProtocol)
 }
 
-pub fn board_line<F>(line: &str, board_size: usize, mut put_stone: F) -> Result<()>
-where
-    F: FnMut(usize, Color),
-{
-    for i in 0..board_size {
-        let idx = 3 + i * 2;
-        match line.get(idx..idx + 1) {
-            Some(ch) => {
-                if ch == "X" {
-                    put_stone(i, Color::Black);
-                } else if ch == "O" {
-                    put_stone(i, Color::White);
-                }
-            }
-            None => return Err(Error::InvalidTextProtocol),
-        }
-    }
-    Ok(())
-}
-
-pub fn next_move(line: &str) -> Result<Color> {
+fn next_move(line: &str) -> Result<Color> {
     if line.starts_with("Next player:") {
         let words: Vec<&str> = line.split_whitespace().collect();
         if words.len() < 3 {
@@ -62,10 +43,264 @@ fn stones_captured(line: &str, prefix: &str) -> Result<u32> {
     Err(Error::InvalidTextProtocol)
 }
 
-pub fn black_captured(line: &str) -> Result<u32> {
+fn black_captured(line: &str) -> Result<u32> {
     stones_captured(line, "B")
 }
 
-pub fn white_captured(line: &str) -> Result<u32> {
+fn white_captured(line: &str) -> Result<u32> {
     stones_captured(line, "W")
 }
+
+// Заголовок с буквами колонок: "   A B C D E F G H J"
+fn column_header(line: &str) -> Option<Vec<usize>> {
+    let columns: Vec<usize> = line
+        .char_indices()
+        .filter(|(_, ch)| !ch.is_whitespace())
+        .map(|(idx, _)| idx)
+        .collect();
+    let letters_only = line
+        .split_whitespace()
+        .all(|word| word.len() == 1 && word.as_bytes()[0].is_ascii_uppercase());
+    if columns.is_empty() || !letters_only {
+        return None;
+    }
+    Some(columns)
+}
+
+fn find_line<'a>(lines: &[&'a str], prefix: &str) -> Result<&'a str> {
+    lines
+        .iter()
+        .find(|line| line.starts_with(prefix))
+        .copied()
+        .ok_or(Error::InvalidTextProtocol)
+}
+
+// Вывод showboard у KataGo. Размер доски берётся из самого вывода: ширина по заголовку
+// с буквами колонок, высота по числу строк с номерами, так что подходит и прямоугольная доска.
+pub fn showboard(text: &str) -> Result<State> {
+    let lines: Vec<&str> = text.lines().collect();
+    let header = lines
+        .iter()
+        .position(|line| column_header(line).is_some())
+        .ok_or(Error::InvalidTextProtocol)?;
+    let columns = column_header(lines[header]).ok_or(Error::InvalidTextProtocol)?;
+
+    // строки доски идут сверху вниз и начинаются со своего номера
+    let rows: Vec<(usize, &str)> = lines[header + 1..]
+        .iter()
+        .map_while(|line| {
+            let number = line.split_whitespace().next()?.parse::<usize>().ok()?;
+            Some((number, *line))
+        })
+        .collect();
+    let height = rows.len();
+    if columns.len() > MAX_SIZE || height > MAX_SIZE {
+        return Err(Error::InvalidTextProtocol);
+    }
+    let mut stones = Grid::new(columns.len(), height, None);
+    for (idx, (number, line)) in rows.into_iter().enumerate() {
+        if number != height - idx {
+            return Err(Error::InvalidTextProtocol);
+        }
+        for (x, &column) in columns.iter().enumerate() {
+            let color = match line.get(column..column + 1) {
+                Some("X") => Some(Color::Black),
+                Some("O") => Some(Color::White),
+                Some(_) => None,
+                None => return Err(Error::InvalidTextProtocol),
+            };
+            stones.set(Position::new(x, number - 1), color);
+        }
+    }
+
+    let rest = &lines[header + 1 + height..];
+    Ok(State {
+        stones,
+        move_num: move_num(lines[0])?,
+        next_move: next_move(find_line(rest, "Next player:")?)?,
+        black_captured: black_captured(find_line(rest, "B stones captured:")?)?,
+        white_captured: white_captured(find_line(rest, "W stones captured:")?)?,
+    })
+}
+
+fn float(value: &str) -> Result<f64> {
+    value.parse::<f64>().map_err(|_| Error::InvalidTextProtocol)
+}
+
+// Строка kata-analyze: "info move Q16 visits 10 winrate 0.48 scoreLead -0.3 ... pv Q16 D4 info move ..."
+pub fn analyze_line(line: &str) -> Result<Vec<MoveInfo>> {
+    let mut res = Vec::new();
+    let mut tokens = line.split_whitespace().peekable();
+    while tokens.next() == Some("info") {
+        let mut mv = None;
+        let mut info = MoveInfo {
+            mv: Move::Pass,
+            visits: 0,
+            winrate: 0.,
+            score_lead: 0.,
+            prior: 0.,
+            order: 0,
+            pv: Vec::new(),
+        };
+        while let Some(&key) = tokens.peek() {
+            if key == "info" || key == "ownership" {
+                break;
+            }
+            tokens.next();
+            // вариант идёт до следующего info и значений не считает
+            if key == "pv" {
+                while let Some(&pv_move) = tokens.peek() {
+                    if pv_move == "info" || pv_move == "ownership" {
+                        break;
+                    }
+                    info.pv.push(Move::from_str(pv_move)?);
+                    tokens.next();
+                }
+                continue;
+            }
+            let value = tokens.next().ok_or(Error::InvalidTextProtocol)?;
+            match key {
+                "move" => mv = Some(Move::from_str(value)?),
+                "visits" => info.visits = value.parse::<u32>()?,
+                "winrate" => info.winrate = float(value)?,
+                "scoreLead" => info.score_lead = float(value)?,
+                "prior" => info.prior = float(value)?,
+                "order" => info.order = value.parse::<u32>()?,
+                _ => {}
+            }
+        }
+        info.mv = mv.ok_or(Error::InvalidTextProtocol)?;
+        res.push(info);
+    }
+    Ok(res)
+}
+
+// Владение в конце строки kata-analyze с "ownership true": по значению на точку
+// от -1 до 1, начиная с верхней строки доски. Доска квадратная, размер по числу значений.
+pub fn ownership(line: &str) -> Result<Grid<f32>> {
+    let values = line
+        .split_whitespace()
+        .skip_while(|&word| word != "ownership")
+        .skip(1)
+        .map(|value| value.parse::<f32>().map_err(|_| Error::InvalidTextProtocol))
+        .collect::<Result<Vec<f32>>>()?;
+    let size = (values.len() as f64).sqrt() as usize;
+    if size == 0 || size > MAX_SIZE || size * size != values.len() {
+        return Err(Error::InvalidTextProtocol);
+    }
+    let mut grid = Grid::new(size, size, 0.);
+    for (idx, value) in values.into_iter().enumerate() {
+        grid.set(Position::new(idx % size, size - idx / size - 1), value);
+    }
+    Ok(grid)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const BOARD_9X9: &str = "\
+MoveNum: 3 HASH: 5A4C2E0B1F3D9E8A7C6B5D4E3F2A1B0C
+   A B C D E F G H J
+ 9 . . . . . . . . .
+ 8 . . . . . . . . .
+ 7 . . X . . . . . .
+ 6 . . . . . . . . .
+ 5 . . . . O2. . . .
+ 4 . . . . . . . . .
+ 3 . . . . . . X3. .
+ 2 . . . . . . . . .
+ 1 . . . . . . . . .
+
+Next player: White
+Rules: {\"friendlyPassOk\":false,\"hasButton\":false,\"ko\":\"POSITIONAL\",\"komi\":7.5}
+B stones captured: 0
+W stones captured: 1
+";
+
+    fn pos(s: &str) -> Position {
+        Position::from_gtp(s).unwrap()
+    }
+
+    // Вывод showboard в формате KataGo, cells это (точка, клетка вроде "X" или "O1")
+    fn board_text(width: usize, height: usize, cells: &[(&str, &str)]) -> String {
+        let mut res = String::from("MoveNum: 0 HASH: 0\n  ");
+        for x in 0..width {
+            res.push_str(&format!(" {}", Position::new(x, 0).column_letter()));
+        }
+        res.push('\n');
+        for y in (0..height).rev() {
+            res.push_str(&format!("{:>2}", y + 1));
+            let mut marked = false;
+            for x in 0..width {
+                let cell = cells
+                    .iter()
+                    .find(|(at, _)| pos(at) == Position::new(x, y))
+                    .map_or(".", |(_, cell)| cell);
+                // отметка последнего хода занимает пробел перед следующей клеткой
+                if !marked {
+                    res.push(' ');
+                }
+                res.push_str(cell);
+                marked = cell.len() > 1;
+            }
+            res.push('\n');
+        }
+        res.push_str("Next player: Black\nB stones captured: 0\nW stones captured: 0\n");
+        res
+    }
+
+    #[test]
+    fn showboard_9x9_with_last_move_markers() {
+        let state = showboard(BOARD_9X9).unwrap();
+        assert_eq!((state.stones.width(), state.stones.height()), (9, 9));
+        assert_eq!(state.move_num, 3);
+        assert_eq!(state.next_move, Color::White);
+        assert_eq!((state.black_captured, state.white_captured), (0, 1));
+        assert_eq!(state.stones.get(pos("C7")), Some(&Some(Color::Black)));
+        assert_eq!(state.stones.get(pos("E5")), Some(&Some(Color::White)));
+        assert_eq!(state.stones.get(pos("G3")), Some(&Some(Color::Black)));
+        // клетки сразу за отметками
+        assert_eq!(state.stones.get(pos("F5")), Some(&None));
+        assert_eq!(state.stones.get(pos("H3")), Some(&None));
+        let stones = state.stones.iter().filter(|(_, stone)| stone.is_some());
+        assert_eq!(stones.count(), 3);
+    }
+
+    #[test]
+    fn showboard_13x13() {
+        let text = board_text(13, 13, &[("A13", "X"), ("N1", "O1"), ("J10", "X")]);
+        let state = showboard(&text).unwrap();
+        let board = state.board().unwrap();
+        assert_eq!(board.size(), 13);
+        assert_eq!(board.stone(pos("A13")), Some(Color::Black));
+        assert_eq!(board.stone(pos("N1")), Some(Color::White));
+        assert_eq!(board.stone(pos("J10")), Some(Color::Black));
+        assert_eq!(board.stone(pos("H10")), None);
+    }
+
+    #[test]
+    fn showboard_non_square() {
+        let text = board_text(5, 3, &[("B2", "X1"), ("E3", "O")]);
+        let state = showboard(&text).unwrap();
+        assert_eq!((state.stones.width(), state.stones.height()), (5, 3));
+        assert_eq!(state.stones.get(pos("B2")), Some(&Some(Color::Black)));
+        assert_eq!(state.stones.get(pos("E3")), Some(&Some(Color::White)));
+        assert!(state.board().is_none());
+    }
+
+    #[test]
+    fn showboard_errors() {
+        let rows_out_of_order = BOARD_9X9.replace(" 8 .", " 7 .");
+        assert!(matches!(
+            showboard(&rows_out_of_order),
+            Err(Error::InvalidTextProtocol)
+        ));
+        let no_player = BOARD_9X9.replace("Next player: White\n", "");
+        assert!(matches!(
+            showboard(&no_player),
+            Err(Error::InvalidTextProtocol)
+        ));
+        assert!(matches!(showboard(""), Err(Error::InvalidTextProtocol)));
+    }
+}
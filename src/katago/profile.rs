@@ -0,0 +1,141 @@
+use std::fmt::{self, Display};
+use std::str::FromStr;
+
+#[derive(PartialEq, Eq, Clone, Copy, Debug)]
+enum Level {
+    Kyu(u8),
+    Dan(u8),
+}
+
+// Сила игры для профиля: от 20 кю до 9 дана любителей.
+// Создаётся только через kyu, dan и разбор строки, так что всегда в допустимых пределах.
+#[derive(PartialEq, Eq, Clone, Copy, Debug)]
+pub struct Rank(Level);
+
+impl Rank {
+    pub fn kyu(kyu: u8) -> Option<Rank> {
+        (1..=20).contains(&kyu).then_some(Rank(Level::Kyu(kyu)))
+    }
+
+    pub fn dan(dan: u8) -> Option<Rank> {
+        (1..=9).contains(&dan).then_some(Rank(Level::Dan(dan)))
+    }
+}
+
+#[derive(PartialEq, Eq, Clone, Copy, Debug)]
+enum Profile {
+    // современные любители заданной силы, rank_5k
+    Rank(Rank),
+    // любители до появления AlphaZero, preaz_3d
+    PreAz(Rank),
+    // профессионалы заданного года, proyear_1990
+    ProYear(u16),
+}
+
+// Каким людям подражает human-модель KataGo, значение humanSLProfile.
+// Год проверяется при создании, поэтому KataGo не получит профиль, которого не знает.
+#[derive(PartialEq, Eq, Clone, Copy, Debug)]
+pub struct HumanProfile(Profile);
+
+impl HumanProfile {
+    pub fn rank(rank: Rank) -> HumanProfile {
+        HumanProfile(Profile::Rank(rank))
+    }
+
+    pub fn pre_az(rank: Rank) -> HumanProfile {
+        HumanProfile(Profile::PreAz(rank))
+    }
+
+    pub fn pro_year(year: u16) -> Option<HumanProfile> {
+        (1800..=2023)
+            .contains(&year)
+            .then_some(HumanProfile(Profile::ProYear(year)))
+    }
+}
+
+#[derive(Debug)]
+pub struct ParseProfileError;
+
+impl FromStr for Rank {
+    type Err = ParseProfileError;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let number = |value: &str| value.parse::<u8>().map_err(|_| ParseProfileError);
+        let rank = if let Some(kyu) = s.strip_suffix('k') {
+            Rank::kyu(number(kyu)?)
+        } else if let Some(dan) = s.strip_suffix('d') {
+            Rank::dan(number(dan)?)
+        } else {
+            None
+        };
+        rank.ok_or(ParseProfileError)
+    }
+}
+
+impl FromStr for HumanProfile {
+    type Err = ParseProfileError;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let (kind, value) = s.trim().split_once('_').ok_or(ParseProfileError)?;
+        match kind {
+            "rank" => Ok(HumanProfile::rank(Rank::from_str(value)?)),
+            "preaz" => Ok(HumanProfile::pre_az(Rank::from_str(value)?)),
+            "proyear" => {
+                let year = value.parse::<u16>().map_err(|_| ParseProfileError)?;
+                HumanProfile::pro_year(year).ok_or(ParseProfileError)
+            }
+            _ => Err(ParseProfileError),
+        }
+    }
+}
+
+impl Display for Rank {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self.0 {
+            Level::Kyu(kyu) => write!(f, "{kyu}k"),
+            Level::Dan(dan) => write!(f, "{dan}d"),
+        }
+    }
+}
+
+impl Display for HumanProfile {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self.0 {
+            Profile::Rank(rank) => write!(f, "rank_{rank}"),
+            Profile::PreAz(rank) => write!(f, "preaz_{rank}"),
+            Profile::ProYear(year) => write!(f, "proyear_{year}"),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn parses_and_prints_profiles() {
+        for text in ["rank_20k", "rank_1d", "preaz_9d", "proyear_1990"] {
+            assert_eq!(HumanProfile::from_str(text).unwrap().to_string(), text);
+        }
+        let profile = HumanProfile::rank(Rank::kyu(5).unwrap());
+        assert_eq!(profile.to_string(), "rank_5k");
+    }
+
+    #[test]
+    fn rejects_out_of_range() {
+        assert!(Rank::kyu(0).is_none());
+        assert!(Rank::kyu(21).is_none());
+        assert!(Rank::dan(10).is_none());
+        assert!(HumanProfile::pro_year(1799).is_none());
+        assert!(HumanProfile::pro_year(2024).is_none());
+        for text in [
+            "rank_30k",
+            "preaz_0d",
+            "proyear_3000",
+            "rank5k",
+            "amateur_5k",
+        ] {
+            assert!(HumanProfile::from_str(text).is_err(), "{text}");
+        }
+    }
+}
@@ -0,0 +1,10 @@
+// Модули собраны в библиотеку, чтобы fake_katago и тесты из tests/ пользовались ими же
+pub mod app;
+pub mod board;
+pub mod clock;
+pub mod config;
+pub mod game;
+pub mod gtp;
+pub mod katago;
+pub mod sgf;
+pub mod vision;
@@ -0,0 +1,167 @@
+use super::board::{Board, Cell, Color, GameResult, Move, PlayError, Position};
+use chrono::{Local, NaiveDate};
+use std::{fmt, fs, io, path::Path};
+
+mod parse;
+mod write;
+
+pub use parse::parse;
+pub use write::{save, to_string};
+
+// Ход в виде пары цвет + действие, как он хранится в записи партии
+pub type Record = (Color, Move);
+
+// Сведения о партии из корневого узла SGF
+pub struct GameInfo {
+    pub size: usize,
+    pub komi: f32,
+    pub black: String,
+    pub white: String,
+    pub date: NaiveDate,
+    pub result: Option<GameResult>,
+    // камни форы, выставленные до первого хода белых
+    pub handicap: Vec<Position>,
+}
+
+impl GameInfo {
+    pub fn new(size: usize) -> GameInfo {
+        GameInfo {
+            size,
+            komi: 7.5,
+            black: String::new(),
+            white: String::new(),
+            date: Local::now().date_naive(),
+            result: None,
+            handicap: Vec::new(),
+        }
+    }
+}
+
+#[derive(Debug)]
+pub enum Error {
+    Io(io::Error),
+    // синтаксическая ошибка и смещение в тексте, где она найдена
+    Syntax(usize),
+    InvalidValue(String),
+    IllegalMove(PlayError),
+    InvalidPath,
+}
+
+impl From<io::Error> for Error {
+    fn from(e: io::Error) -> Error {
+        Error::Io(e)
+    }
+}
+
+impl From<PlayError> for Error {
+    fn from(e: PlayError) -> Error {
+        Error::IllegalMove(e)
+    }
+}
+
+impl fmt::Display for Error {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Error::Io(e) => write!(f, "{e}"),
+            Error::Syntax(offset) => write!(f, "syntax error at offset {offset}"),
+            Error::InvalidValue(value) => write!(f, "invalid value {value}"),
+            Error::IllegalMove(e) => write!(f, "illegal move: {e}"),
+            Error::InvalidPath => write!(f, "no such variation"),
+        }
+    }
+}
+
+pub type Result<T> = std::result::Result<T, Error>;
+
+// Узел дерева партии: ход, расстановка камней и комментарий.
+// Первый потомок продолжает основной вариант, остальные это ответвления.
+#[derive(Default)]
+pub struct Node {
+    pub mv: Option<Record>,
+    pub add_black: Vec<Position>,
+    pub add_white: Vec<Position>,
+    pub clear: Vec<Position>,
+    pub comment: Option<String>,
+    pub children: Vec<Node>,
+}
+
+impl Node {
+    // Применяет расстановку и ход узла к доске
+    pub fn apply(&self, board: &mut Board) -> Result<()> {
+        for &pos in &self.clear {
+            board.set(pos, Cell::empty());
+        }
+        for &pos in &self.add_black {
+            board.set(pos, Cell::black_stone());
+        }
+        for &pos in &self.add_white {
+            board.set(pos, Cell::white_stone());
+        }
+        match self.mv {
+            Some((color, Move::Play(pos))) => {
+                board.play(color, pos)?;
+            }
+            Some((color, Move::Pass)) => board.pass(color),
+            Some((_, Move::Resign)) | None => {}
+        }
+        Ok(())
+    }
+}
+
+pub struct GameTree {
+    pub info: GameInfo,
+    pub root: Node,
+}
+
+impl GameTree {
+    // Узлы по пути из корня, path это номер выбранного потомка на каждом шаге
+    pub fn nodes(&self, path: &[usize]) -> Result<Vec<&Node>> {
+        let mut node = &self.root;
+        let mut res = vec![node];
+        for &idx in path {
+            node = node.children.get(idx).ok_or(Error::InvalidPath)?;
+            res.push(node);
+        }
+        Ok(res)
+    }
+
+    // Основной вариант от корня до последнего узла
+    pub fn main_line(&self) -> Vec<&Node> {
+        let mut node = &self.root;
+        let mut res = vec![node];
+        while let Some(next) = node.children.first() {
+            node = next;
+            res.push(node);
+        }
+        res
+    }
+
+    // Ходы основного варианта, которые можно по очереди отдать движку
+    pub fn main_line_moves(&self) -> Vec<Record> {
+        self.main_line().iter().filter_map(|node| node.mv).collect()
+    }
+
+    // Позиция на доске после узла, до которого ведёт path
+    pub fn board_at(&self, path: &[usize]) -> Result<Board> {
+        let mut board = Board::new_with_size(self.info.size);
+        for &pos in &self.info.handicap {
+            board.set(pos, Cell::black_stone());
+        }
+        for node in self.nodes(path)? {
+            node.apply(&mut board)?;
+        }
+        Ok(board)
+    }
+}
+
+pub fn load(path: impl AsRef<Path>) -> Result<GameTree> {
+    let text = fs::read_to_string(path)?;
+    parse(&text)
+}
+
+fn color_letter(color: Color) -> char {
+    match color {
+        Color::Black => 'B',
+        Color::White => 'W',
+    }
+}
@@ -0,0 +1,347 @@
+use super::{Color, Error, GameInfo, GameResult, GameTree, Move, Node, Position, Result};
+use crate::board::MAX_SIZE;
+use chrono::NaiveDate;
+use std::str::FromStr;
+
+type Properties = Vec<(String, Vec<String>)>;
+
+// Дерево в том виде, как оно записано в файле: последовательность узлов и варианты после неё
+struct RawTree {
+    nodes: Vec<Properties>,
+    variations: Vec<RawTree>,
+}
+
+struct Parser<'a> {
+    text: &'a [u8],
+    pos: usize,
+}
+
+impl<'a> Parser<'a> {
+    fn skip_whitespace(&mut self) {
+        while self.pos < self.text.len() && self.text[self.pos].is_ascii_whitespace() {
+            self.pos += 1;
+        }
+    }
+
+    fn peek(&mut self) -> Option<u8> {
+        self.skip_whitespace();
+        self.text.get(self.pos).copied()
+    }
+
+    fn expect(&mut self, ch: u8) -> Result<()> {
+        if self.peek() != Some(ch) {
+            return Err(Error::Syntax(self.pos));
+        }
+        self.pos += 1;
+        Ok(())
+    }
+
+    fn tree(&mut self) -> Result<RawTree> {
+        self.expect(b'(')?;
+        let mut tree = RawTree {
+            nodes: Vec::new(),
+            variations: Vec::new(),
+        };
+        while self.peek() == Some(b';') {
+            self.pos += 1;
+            tree.nodes.push(self.properties()?);
+        }
+        if tree.nodes.is_empty() {
+            return Err(Error::Syntax(self.pos));
+        }
+        while self.peek() == Some(b'(') {
+            tree.variations.push(self.tree()?);
+        }
+        self.expect(b')')?;
+        Ok(tree)
+    }
+
+    fn properties(&mut self) -> Result<Properties> {
+        let mut res = Properties::new();
+        while let Some(ch) = self.peek() {
+            if !ch.is_ascii_alphabetic() {
+                break;
+            }
+            let mut ident = String::new();
+            while let Some(&ch) = self.text.get(self.pos) {
+                if !ch.is_ascii_alphabetic() {
+                    break;
+                }
+                // в старых версиях SGF допускались строчные буквы в названиях, они не значимы
+                if ch.is_ascii_uppercase() {
+                    ident.push(ch as char);
+                }
+                self.pos += 1;
+            }
+            let mut values = Vec::new();
+            while self.peek() == Some(b'[') {
+                values.push(self.value()?);
+            }
+            if values.is_empty() {
+                return Err(Error::Syntax(self.pos));
+            }
+            res.push((ident, values));
+        }
+        Ok(res)
+    }
+
+    fn value(&mut self) -> Result<String> {
+        self.expect(b'[')?;
+        let mut bytes = Vec::new();
+        loop {
+            let ch = *self.text.get(self.pos).ok_or(Error::Syntax(self.pos))?;
+            self.pos += 1;
+            match ch {
+                b']' => break,
+                b'\\' => {
+                    let escaped = *self.text.get(self.pos).ok_or(Error::Syntax(self.pos))?;
+                    self.pos += 1;
+                    // экранированный перевод строки это мягкий перенос, его выбрасываем
+                    if escaped == b'\n' || escaped == b'\r' {
+                        continue;
+                    }
+                    bytes.push(escaped);
+                }
+                _ => bytes.push(ch),
+            }
+        }
+        Ok(String::from_utf8_lossy(&bytes).into_owned())
+    }
+}
+
+fn property<'p>(props: &'p Properties, ident: &str) -> Option<&'p Vec<String>> {
+    props
+        .iter()
+        .find(|(name, _)| name == ident)
+        .map(|(_, values)| values)
+}
+
+fn first_value<'p>(props: &'p Properties, ident: &str) -> Option<&'p str> {
+    property(props, ident).map(|values| values[0].as_str())
+}
+
+fn point(value: &str, size: usize) -> Result<Position> {
+    Position::from_sgf(value, size).map_err(|_| Error::InvalidValue(value.to_string()))
+}
+
+// Список точек, в том числе сжатый в прямоугольники вида "aa:cc"
+fn points(values: &[String], size: usize) -> Result<Vec<Position>> {
+    let mut res = Vec::new();
+    for value in values {
+        match value.split_once(':') {
+            Some((from, to)) => {
+                let from = point(from, size)?;
+                let to = point(to, size)?;
+                for x in from.x().min(to.x())..=from.x().max(to.x()) {
+                    for y in from.y().min(to.y())..=from.y().max(to.y()) {
+                        res.push(Position::new(x, y));
+                    }
+                }
+            }
+            None => res.push(point(value, size)?),
+        }
+    }
+    Ok(res)
+}
+
+fn node_move(props: &Properties, size: usize) -> Result<Option<(Color, Move)>> {
+    for (ident, color) in [("B", Color::Black), ("W", Color::White)] {
+        if let Some(value) = first_value(props, ident) {
+            // "tt" на досках до 19x19 тоже означает пас
+            if value.is_empty() || (value == "tt" && size <= 19) {
+                return Ok(Some((color, Move::Pass)));
+            }
+            return Ok(Some((color, Move::Play(point(value, size)?))));
+        }
+    }
+    Ok(None)
+}
+
+fn build_node(props: &Properties, size: usize) -> Result<Node> {
+    let setup = |ident| match property(props, ident) {
+        Some(values) => points(values, size),
+        None => Ok(Vec::new()),
+    };
+    Ok(Node {
+        mv: node_move(props, size)?,
+        add_black: setup("AB")?,
+        add_white: setup("AW")?,
+        clear: setup("AE")?,
+        comment: first_value(props, "C").map(String::from),
+        children: Vec::new(),
+    })
+}
+
+// Превращает последовательность узлов в цепочку, последний узел которой ветвится на варианты
+fn build_tree(tree: &RawTree, size: usize) -> Result<Node> {
+    let mut children = Vec::new();
+    for variation in &tree.variations {
+        children.push(build_tree(variation, size)?);
+    }
+    let mut node = None;
+    for props in tree.nodes.iter().rev() {
+        let mut current = build_node(props, size)?;
+        current.children = match node.take() {
+            Some(next) => vec![next],
+            None => std::mem::take(&mut children),
+        };
+        node = Some(current);
+    }
+    node.ok_or(Error::Syntax(0))
+}
+
+fn game_info(root: &Properties) -> Result<GameInfo> {
+    let size = match first_value(root, "SZ") {
+        Some(value) => value
+            .trim()
+            .parse::<usize>()
+            .map_err(|_| Error::InvalidValue(value.to_string()))?,
+        None => 19,
+    };
+    if !(1..=MAX_SIZE).contains(&size) {
+        return Err(Error::InvalidValue(format!("SZ[{size}]")));
+    }
+    let mut info = GameInfo::new(size);
+    if let Some(value) = first_value(root, "KM") {
+        info.komi = value
+            .trim()
+            .parse::<f32>()
+            .map_err(|_| Error::InvalidValue(value.to_string()))?;
+    }
+    if let Some(value) = first_value(root, "PB") {
+        info.black = value.to_string();
+    }
+    if let Some(value) = first_value(root, "PW") {
+        info.white = value.to_string();
+    }
+    // DT может содержать несколько дат через запятую, берём первую
+    if let Some(value) = first_value(root, "DT") {
+        let date = value.get(..10).unwrap_or(value);
+        if let Ok(date) = NaiveDate::parse_from_str(date, "%Y-%m-%d") {
+            info.date = date;
+        }
+    }
+    if let Some(value) = first_value(root, "RE") {
+        info.result =
+            Some(GameResult::from_str(value).map_err(|_| Error::InvalidValue(value.to_string()))?);
+    }
+    Ok(info)
+}
+
+pub fn parse(text: &str) -> Result<GameTree> {
+    let mut parser = Parser {
+        text: text.as_bytes(),
+        pos: 0,
+    };
+    // до первой скобки может быть любой мусор, например заголовок письма
+    while parser.pos < parser.text.len() && parser.text[parser.pos] != b'(' {
+        parser.pos += 1;
+    }
+    let raw = parser.tree()?;
+    let mut info = game_info(&raw.nodes[0])?;
+    let mut root = build_tree(&raw, info.size)?;
+
+    // камни форы из корня переносим в сведения о партии, как их пишет write
+    let handicap = first_value(&raw.nodes[0], "HA")
+        .and_then(|value| value.trim().parse::<u32>().ok())
+        .unwrap_or(0);
+    if handicap > 0 {
+        info.handicap = std::mem::take(&mut root.add_black);
+    }
+    Ok(GameTree { info, root })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn pos(s: &str) -> Position {
+        Position::from_gtp(s).unwrap()
+    }
+
+    #[test]
+    fn game_info_and_moves() {
+        let tree = parse(
+            "(;GM[1]SZ[9]KM[6.5]PB[Alice]PW[Bob]DT[2024-03-01,2024-03-02]RE[W+R];B[ee];W[cc])",
+        )
+        .unwrap();
+        assert_eq!(tree.info.size, 9);
+        assert_eq!(tree.info.komi, 6.5);
+        assert_eq!(tree.info.black, "Alice");
+        assert_eq!(tree.info.white, "Bob");
+        assert_eq!(tree.info.date.to_string(), "2024-03-01");
+        assert_eq!(tree.info.result, Some(GameResult::Resign(Color::White)));
+        assert_eq!(
+            tree.main_line_moves(),
+            vec![
+                (Color::Black, Move::Play(pos("E5"))),
+                (Color::White, Move::Play(pos("C7")))
+            ]
+        );
+    }
+
+    #[test]
+    fn variations() {
+        let tree = parse("(;SZ[9];B[ee](;W[cc];B[gg])(;W[gc]))").unwrap();
+        let first = &tree.root.children[0];
+        assert_eq!(first.children.len(), 2);
+        assert_eq!(tree.main_line().len(), 4);
+        let side = tree.nodes(&[0, 1]).unwrap();
+        assert_eq!(side[2].mv, Some((Color::White, Move::Play(pos("G7")))));
+        assert!(matches!(tree.nodes(&[0, 2]), Err(Error::InvalidPath)));
+    }
+
+    #[test]
+    fn setup_properties_and_compressed_points() {
+        let tree = parse("(;SZ[9]AB[aa:bc][ee]AW[ii];AE[ab]C[a \\] b])").unwrap();
+        let root = &tree.root;
+        assert_eq!(root.add_black.len(), 7);
+        assert!(root.add_black.contains(&pos("B7")));
+        assert!(root.add_black.contains(&pos("E5")));
+        assert_eq!(root.add_white, vec![pos("J1")]);
+        let node = &root.children[0];
+        assert_eq!(node.clear, vec![pos("A8")]);
+        assert_eq!(node.comment.as_deref(), Some("a ] b"));
+
+        let board = tree.board_at(&[0]).unwrap();
+        assert_eq!(board.stone(pos("A9")), Some(Color::Black));
+        assert_eq!(board.stone(pos("A8")), None);
+    }
+
+    #[test]
+    fn passes() {
+        let tree = parse("(;SZ[19];B[tt];W[])").unwrap();
+        assert_eq!(
+            tree.main_line_moves(),
+            vec![(Color::Black, Move::Pass), (Color::White, Move::Pass)]
+        );
+    }
+
+    #[test]
+    fn handicap_goes_to_info() {
+        let tree = parse("(;SZ[9]HA[2]AB[cc][gg];W[ee])").unwrap();
+        assert_eq!(tree.info.handicap, vec![pos("C7"), pos("G3")]);
+        assert!(tree.root.add_black.is_empty());
+        let board = tree.board_at(&[0]).unwrap();
+        assert_eq!(board.stone(pos("C7")), Some(Color::Black));
+    }
+
+    #[test]
+    fn skips_junk_before_tree() {
+        let tree = parse("From: someone\nSubject: game\n\n(;SZ[9];B[ee])").unwrap();
+        assert_eq!(tree.main_line_moves().len(), 1);
+    }
+
+    #[test]
+    fn syntax_errors() {
+        assert!(matches!(parse("(;SZ[9];B[ee]"), Err(Error::Syntax(_))));
+        assert!(matches!(parse("(;SZ[9]B)"), Err(Error::Syntax(_))));
+        assert!(matches!(parse("()"), Err(Error::Syntax(_))));
+        assert!(matches!(
+            parse("(;SZ[9];B[zz])"),
+            Err(Error::InvalidValue(_))
+        ));
+        assert!(matches!(parse("(;SZ[26])"), Err(Error::InvalidValue(_))));
+        assert!(matches!(parse("(;SZ[0])"), Err(Error::InvalidValue(_))));
+    }
+}
@@ -0,0 +1,145 @@
+use super::{GameInfo, Move, Record, color_letter};
+use std::{fs, io, path::Path};
+
+// сколько ходов писать в одной строке файла
+const MOVES_PER_LINE: usize = 10;
+
+fn escape(text: &str) -> String {
+    text.replace('\\', "\\\\").replace(']', "\\]")
+}
+
+pub fn to_string(info: &GameInfo, moves: &[Record]) -> String {
+    let mut res = String::from("(;FF[4]GM[1]CA[UTF-8]AP[robogo]");
+    res.push_str(&format!("SZ[{}]KM[{}]", info.size, info.komi));
+    if !info.black.is_empty() {
+        res.push_str(&format!("PB[{}]", escape(&info.black)));
+    }
+    if !info.white.is_empty() {
+        res.push_str(&format!("PW[{}]", escape(&info.white)));
+    }
+    res.push_str(&format!("DT[{}]", info.date.format("%Y-%m-%d")));
+    if let Some(result) = &info.result {
+        res.push_str(&format!("RE[{}]", result));
+    }
+    if !info.handicap.is_empty() {
+        res.push_str(&format!("HA[{}]AB", info.handicap.len()));
+        for &pos in &info.handicap {
+            res.push_str(&format!("[{}]", pos.to_sgf(info.size)));
+        }
+    }
+    res.push('\n');
+
+    let mut count = 0;
+    for &(color, mv) in moves {
+        // сдача записывается в RE, отдельного хода для неё в SGF нет
+        let value = match mv {
+            Move::Play(pos) => pos.to_sgf(info.size),
+            Move::Pass => String::new(),
+            Move::Resign => continue,
+        };
+        res.push_str(&format!(";{}[{}]", color_letter(color), value));
+        count += 1;
+        if count % MOVES_PER_LINE == 0 {
+            res.push('\n');
+        }
+    }
+    res.push_str(")\n");
+    res
+}
+
+pub fn save(path: impl AsRef<Path>, info: &GameInfo, moves: &[Record]) -> io::Result<()> {
+    fs::write(path, to_string(info, moves))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::super::{GameTree, parse};
+    use super::*;
+    use crate::board::{Color, GameResult, Position};
+    use chrono::NaiveDate;
+
+    fn pos(s: &str) -> Position {
+        Position::from_gtp(s).unwrap()
+    }
+
+    fn round_trip(info: &GameInfo, moves: &[Record]) -> GameTree {
+        let tree = parse(&to_string(info, moves)).unwrap();
+        assert_eq!(tree.info.size, info.size);
+        assert_eq!(tree.info.komi, info.komi);
+        assert_eq!(tree.info.black, info.black);
+        assert_eq!(tree.info.white, info.white);
+        assert_eq!(tree.info.date, info.date);
+        assert_eq!(tree.info.result, info.result);
+        assert_eq!(tree.info.handicap, info.handicap);
+        tree
+    }
+
+    #[test]
+    fn moves_and_passes() {
+        let mut info = GameInfo::new(19);
+        info.date = NaiveDate::from_ymd_opt(2024, 5, 17).unwrap();
+        // больше MOVES_PER_LINE ходов, чтобы в файле был перенос строки
+        let mut moves = Vec::new();
+        for idx in 0..12 {
+            let color = if idx % 2 == 0 {
+                Color::Black
+            } else {
+                Color::White
+            };
+            moves.push((color, Move::Play(Position::new(idx, idx))));
+        }
+        moves.push((Color::Black, Move::Pass));
+        moves.push((Color::White, Move::Pass));
+        let tree = round_trip(&info, &moves);
+        assert_eq!(tree.main_line_moves(), moves);
+    }
+
+    #[test]
+    fn resign_goes_to_result() {
+        let mut info = GameInfo::new(9);
+        info.result = Some(GameResult::Resign(Color::White));
+        let moves = vec![
+            (Color::Black, Move::Play(pos("E5"))),
+            (Color::Black, Move::Resign),
+        ];
+        let tree = round_trip(&info, &moves);
+        assert_eq!(tree.main_line_moves(), moves[..1]);
+    }
+
+    #[test]
+    fn handicap_komi_and_result() {
+        let mut info = GameInfo::new(13);
+        info.komi = 0.5;
+        info.handicap = vec![pos("D4"), pos("K10")];
+        info.result = Some(GameResult::Score(Color::Black, 3.5));
+        let moves = vec![(Color::White, Move::Play(pos("K4")))];
+        let tree = round_trip(&info, &moves);
+        assert_eq!(tree.main_line_moves(), moves);
+        let board = tree.board_at(&[0]).unwrap();
+        assert_eq!(board.stone(pos("D4")), Some(Color::Black));
+        assert_eq!(board.stone(pos("K4")), Some(Color::White));
+    }
+
+    #[test]
+    fn escapes_text_values() {
+        let mut info = GameInfo::new(9);
+        info.black = String::from("a]b");
+        info.white = String::from("c\\d\\]");
+        round_trip(&info, &[]);
+    }
+
+    #[test]
+    fn board_sizes() {
+        for size in [5, 9, 19, 25] {
+            let mut info = GameInfo::new(size);
+            info.result = Some(GameResult::Draw);
+            let corner = Position::new(size - 1, size - 1);
+            let moves = vec![
+                (Color::Black, Move::Play(corner)),
+                (Color::White, Move::Play(Position::new(0, 0))),
+            ];
+            let tree = round_trip(&info, &moves);
+            assert_eq!(tree.main_line_moves(), moves, "{size}");
+        }
+    }
+}
@@ -0,0 +1,352 @@
+// Калибровка распознавания под конкретную доску, камеру и освещение.
+// Сначала камера видит пустую доску: по ней подбираются порог бинаризации и сетка.
+// Потом на доску ставятся камни из calibration_stones: по ним подбираются пороги L* и цвета.
+
+use super::{
+    GridLines, Polygon, Settings, find_board_border, find_grid_lines, find_stones, sample_point,
+    warp_board_by_border,
+};
+use crate::board::{Board, Cell, Color, Position};
+use opencv::{core, highgui, imgproc, prelude::*, videoio};
+use std::{fmt, io};
+
+#[derive(Debug)]
+pub enum Error {
+    OpenCv(opencv::Error),
+    Io(io::Error),
+    // ни при одном пороге не нашлась рамка доски
+    BorderNotFound,
+    // на выровненной пустой доске не нашлись линии сетки
+    GridNotFound,
+    // камни не отделяются от пустых точек по светлоте
+    Inseparable(Color),
+    // между чёрными и белыми камнями не помещаются два разных порога
+    ThresholdsOverlap,
+    // с подобранными настройками камни распознаются не на своих местах
+    Mismatch(Vec<Position>),
+    // пользователь закрыл калибровку
+    Aborted,
+    // на доске меньше MIN_BOARD_SIZE линий калибровочные камни не расставить
+    BoardTooSmall(usize),
+}
+
+impl From<opencv::Error> for Error {
+    fn from(e: opencv::Error) -> Self {
+        Error::OpenCv(e)
+    }
+}
+
+impl From<io::Error> for Error {
+    fn from(e: io::Error) -> Self {
+        Error::Io(e)
+    }
+}
+
+impl fmt::Display for Error {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Error::OpenCv(e) => write!(f, "opencv: {e}"),
+            Error::Io(e) => write!(f, "{e}"),
+            Error::BorderNotFound => write!(f, "board border not found"),
+            Error::GridNotFound => write!(f, "grid lines not found on the empty board"),
+            Error::Inseparable(color) => {
+                write!(f, "{color} stones can not be told apart from empty points")
+            }
+            Error::ThresholdsOverlap => {
+                write!(f, "stones are too close in lightness to the empty points")
+            }
+            Error::Mismatch(points) => {
+                let points: Vec<String> = points.iter().map(|pos| pos.to_gtp()).collect();
+                write!(f, "recognized wrong at {}", points.join(" "))
+            }
+            Error::Aborted => write!(f, "calibration aborted"),
+            Error::BoardTooSmall(size) => {
+                write!(
+                    f,
+                    "board {size}x{size} is smaller than {MIN_BOARD_SIZE}x{MIN_BOARD_SIZE}"
+                )
+            }
+        }
+    }
+}
+
+pub type Result<T> = std::result::Result<T, Error>;
+
+// Камни у края, в центре и у другого края должны стоять на разных точках
+pub const MIN_BOARD_SIZE: usize = 7;
+
+// Камни, которые нужно поставить на доску для второго шага калибровки
+pub fn calibration_stones(size: usize) -> Result<Board> {
+    if size < MIN_BOARD_SIZE {
+        return Err(Error::BoardTooSmall(size));
+    }
+    let near = if size >= 13 { 3 } else { 2 };
+    let far = size - near - 1;
+    let center = size / 2;
+    let mut board = Board::new_with_size(size);
+    for (x, y) in [(near, near), (center, center), (far, far)] {
+        board.set(Position::new(x, y), Cell::black_stone());
+    }
+    for (x, y) in [(near, far), (far, near)] {
+        board.set(Position::new(x, y), Cell::white_stone());
+    }
+    Ok(board)
+}
+
+// Перебирает пороги бинаризации и берёт середину самого длинного ряда порогов,
+// при которых находится рамка почти наибольшего периметра
+fn fit_binary_threshold(settings: &mut Settings, img: &Mat) -> Result<Polygon> {
+    let mut probe = settings.clone();
+    probe.is_dump_steps = false;
+    probe.min_board_border_perimeter = 0.;
+    let mut found = Vec::new();
+    for threshold in (20..=235).step_by(5) {
+        probe.binary_threshold = threshold as f64;
+        let perimeter = match find_board_border(&probe, img)? {
+            Some(border) => imgproc::arc_length(&border, true)?,
+            None => 0.,
+        };
+        found.push((threshold as f64, perimeter));
+    }
+    let max_perimeter = found
+        .iter()
+        .map(|&(_, perimeter)| perimeter)
+        .fold(0., f64::max);
+    if max_perimeter <= 0. {
+        return Err(Error::BorderNotFound);
+    }
+
+    let mut best: &[(f64, f64)] = &[];
+    for run in found.split(|&(_, perimeter)| perimeter < max_perimeter * 0.97) {
+        if run.len() > best.len() {
+            best = run;
+        }
+    }
+    let (threshold, perimeter) = best[best.len() / 2];
+    settings.binary_threshold = threshold;
+    // рамка заметно меньше найденной уже не доска
+    settings.min_board_border_perimeter = perimeter * 0.7;
+
+    probe.binary_threshold = threshold;
+    find_board_border(&probe, img)?.ok_or(Error::BorderNotFound)
+}
+
+// Находит сетку на пустой доске и переводит её в отступы и радиус камня для настроек
+fn fit_grid(settings: &mut Settings, warped: &Mat, size: usize) -> Result<GridLines> {
+    let lines = find_grid_lines(settings, warped, size, size)?.ok_or(Error::GridNotFound)?;
+    let (horz_step, vert_step) = lines.steps();
+    let last = size - 1;
+    settings.stones_left_shift = lines.vertical[0] - horz_step / 2.;
+    settings.stones_right_shift = warped.cols() as f64 - lines.vertical[last] - horz_step / 2.;
+    settings.stones_top_shift = lines.horizontal[0] - vert_step / 2.;
+    settings.stones_bottom_shift = warped.rows() as f64 - lines.horizontal[last] - vert_step / 2.;
+    // круг заметно меньше камня, чтобы не задевать соседние точки
+    settings.stone_radius = (horz_step.min(vert_step) * 0.3) as i32;
+    Ok(lines)
+}
+
+fn lightness(samples: &[(u8, u8)]) -> impl Iterator<Item = u16> + '_ {
+    samples.iter().map(|&(l, _)| l as u16)
+}
+
+fn chroma(samples: &[(u8, u8)]) -> impl Iterator<Item = u16> + '_ {
+    samples.iter().map(|&(_, color)| color as u16)
+}
+
+// Пороги L* для чёрных и белых камней посередине между камнями и пустыми точками.
+// find_stones проверяет l < black и l > white, а Settings::validate требует black < white.
+fn lightness_thresholds(
+    black_max: u16,
+    white_min: u16,
+    empty_min: u16,
+    empty_max: u16,
+) -> Result<(u8, u8)> {
+    if black_max >= empty_min {
+        return Err(Error::Inseparable(Color::Black));
+    }
+    if white_min <= empty_max {
+        return Err(Error::Inseparable(Color::White));
+    }
+    let black = (black_max + empty_min + 1) / 2;
+    let white = (white_min + empty_max) / 2;
+    // совпадают, когда все пустые точки одной светлоты и камни отличаются от них на единицу
+    if black >= white {
+        return Err(Error::ThresholdsOverlap);
+    }
+    Ok((black as u8, white as u8))
+}
+
+// Пороги L* и насыщенности посередине между камнями и пустыми точками
+fn fit_stone_thresholds(
+    settings: &mut Settings,
+    warped: &Mat,
+    lines: &GridLines,
+    expected: &Board,
+) -> Result<()> {
+    let mut lab = Mat::default();
+    imgproc::cvt_color(warped, &mut lab, imgproc::COLOR_BGR2Lab, 0)?;
+    let mut mask = Mat::zeros(warped.rows(), warped.cols(), core::CV_8UC1)?.to_mat()?;
+
+    let size = expected.size();
+    let mut black = Vec::new();
+    let mut white = Vec::new();
+    let mut empty = Vec::new();
+    for x in 0..size {
+        for y in 0..size {
+            let center = lines.intersection(x, size - y - 1);
+            let sample = sample_point(&lab, &mut mask, center, settings.stone_radius)?;
+            match expected.stone(Position::new(x, y)) {
+                Some(Color::Black) => black.push(sample),
+                Some(Color::White) => white.push(sample),
+                None => empty.push(sample),
+            }
+        }
+    }
+
+    let (black_threshold, white_threshold) = lightness_thresholds(
+        lightness(&black).max().unwrap_or(0),
+        lightness(&white).min().unwrap_or(255),
+        lightness(&empty).min().unwrap_or(255),
+        lightness(&empty).max().unwrap_or(0),
+    )?;
+    settings.black_stone_threshold = black_threshold;
+    settings.white_stone_threshold = white_threshold;
+
+    // камни серые, а дерево доски цветное, если цвет их не разделяет, хватает светлоты
+    let stones_max = chroma(&black).chain(chroma(&white)).max().unwrap_or(0);
+    let empty_chroma = chroma(&empty).min().unwrap_or(0);
+    settings.min_color_threshold = if empty_chroma > stones_max {
+        ((stones_max + empty_chroma) / 2) as u8
+    } else {
+        stones_max as u8
+    };
+    Ok(())
+}
+
+// Подбирает настройки по кадру с пустой доской и кадру с камнями calibration_stones(size).
+// Камера между кадрами не двигается, поэтому рамка доски берётся с пустой доски.
+pub fn calibrate(settings: &Settings, empty: &Mat, stones: &Mat, size: usize) -> Result<Settings> {
+    let expected = calibration_stones(size)?;
+    let mut res = settings.clone();
+    let border = fit_binary_threshold(&mut res, empty)?;
+    let warped_empty = warp_board_by_border(&res, &border, empty)?;
+    let lines = fit_grid(&mut res, &warped_empty, size)?;
+
+    let warped_stones = warp_board_by_border(&res, &border, stones)?;
+    fit_stone_thresholds(&mut res, &warped_stones, &lines, &expected)?;
+
+    // с новыми настройками камни должны распознаваться ровно там, где стоят
+    let recognized = find_stones(&res, &warped_stones, size)?;
+    let wrong: Vec<Position> = (0..size * size)
+        .map(|idx| Position::new(idx % size, idx / size))
+        .filter(|&pos| recognized.stone(pos) != expected.stone(pos))
+        .collect();
+    if !wrong.is_empty() {
+        return Err(Error::Mismatch(wrong));
+    }
+    Ok(res)
+}
+
+// Показывает камеру с подсказкой, пока пользователь не нажмёт пробел, Esc прерывает
+fn capture(cam: &mut videoio::VideoCapture, prompt: &str) -> Result<Mat> {
+    let mut frame = Mat::default();
+    loop {
+        cam.read(&mut frame)?;
+        if frame.empty() {
+            continue;
+        }
+        let mut shown = frame.clone();
+        imgproc::put_text(
+            &mut shown,
+            prompt,
+            core::Point::new(30, 50),
+            imgproc::FONT_HERSHEY_SIMPLEX,
+            1.2,
+            core::Scalar::new(0.0, 0.0, 255.0, 0.0),
+            2,
+            imgproc::LINE_AA,
+            false,
+        )?;
+        highgui::imshow("Calibration", &shown)?;
+        match highgui::wait_key(10)? {
+            32 => return Ok(frame),
+            27 => return Err(Error::Aborted),
+            _ => {}
+        }
+    }
+}
+
+// Калибровка с камеры cam, начиная с settings: кадр пустой доски, потом кадр
+// с камнями calibration_stones(board_size). Результат стоит сохранить в файл настроек.
+pub fn run(
+    settings: &Settings,
+    cam: &mut videoio::VideoCapture,
+    board_size: usize,
+) -> Result<Settings> {
+    calibration_stones(board_size)?;
+    highgui::named_window("Calibration", highgui::WINDOW_NORMAL)?;
+    let empty = capture(cam, "empty board: press space")?;
+    let with_stones = capture(cam, "stones placed: press space")?;
+    highgui::destroy_window("Calibration")?;
+
+    calibrate(settings, &empty, &with_stones, board_size)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn stones_on_distinct_points() {
+        for size in [MIN_BOARD_SIZE, 9, 13, 19] {
+            let board = calibration_stones(size).unwrap();
+            let count = |color| {
+                (0..size * size)
+                    .filter(|&idx| {
+                        board.stone(Position::new(idx % size, idx / size)) == Some(color)
+                    })
+                    .count()
+            };
+            assert_eq!((count(Color::Black), count(Color::White)), (3, 2), "{size}");
+        }
+    }
+
+    #[test]
+    fn small_board_rejected() {
+        for size in [1, 2, 5, MIN_BOARD_SIZE - 1] {
+            assert!(matches!(
+                calibration_stones(size),
+                Err(Error::BoardTooSmall(small)) if small == size
+            ));
+        }
+    }
+
+    #[test]
+    fn lightness_thresholds_between_stones_and_empty() {
+        assert!(matches!(
+            lightness_thresholds(40, 220, 120, 160),
+            Ok((80, 190))
+        ));
+        assert!(matches!(
+            lightness_thresholds(130, 220, 120, 160),
+            Err(Error::Inseparable(Color::Black))
+        ));
+        assert!(matches!(
+            lightness_thresholds(40, 150, 120, 160),
+            Err(Error::Inseparable(Color::White))
+        ));
+    }
+
+    #[test]
+    fn equal_lightness_thresholds_rejected() {
+        // все пустые точки светлоты 120
+        assert!(matches!(
+            lightness_thresholds(118, 121, 120, 120),
+            Ok((119, 120))
+        ));
+        assert!(matches!(
+            lightness_thresholds(119, 121, 120, 120),
+            Err(Error::ThresholdsOverlap)
+        ));
+    }
+}
@@ -0,0 +1,646 @@
+use opencv::{
+    Result,
+    core::{self, Point, Point2f, Rect, Scalar, Size, Vector, convert_to_direct_3d_surface9_def},
+    imgproc,
+    prelude::*,
+};
+
+use super::board::*;
+use serde::{Deserialize, Serialize};
+
+pub mod calibrate;
+
+type Polygon = Vector<Point>;
+
+// Поля, которых нет в файле настроек, берутся из Settings::default()
+#[derive(Clone, Debug, Serialize, Deserialize)]
+#[serde(default)]
+pub struct Settings {
+    binary_threshold: f64,
+    min_board_border_perimeter: f64,
+    board_width: i32,
+    board_height: i32,
+    stones_left_shift: f64,
+    stones_right_shift: f64,
+    stones_top_shift: f64,
+    stones_bottom_shift: f64,
+    // размер окна и сдвиг адаптивного порога при поиске линий сетки
+    grid_block_size: i32,
+    grid_threshold: f64,
+    // во сколько раз линии сетки должны быть темнее среднего, чтобы им поверить
+    grid_min_contrast: f64,
+    stone_radius: i32,
+    white_stone_threshold: u8,
+    black_stone_threshold: u8,
+    min_color_threshold: u8,
+    is_dump_steps: bool,
+    dump_dir: String,
+}
+
+impl Default for Settings {
+    fn default() -> Settings {
+        Settings {
+            binary_threshold: 120.,
+            min_board_border_perimeter: 2500.,
+            board_width: 1000,
+            board_height: 1000,
+            stones_left_shift: 17.,
+            stones_right_shift: 16.,
+            stones_top_shift: 17.,
+            stones_bottom_shift: 16.,
+            grid_block_size: 15,
+            grid_threshold: 10.,
+            grid_min_contrast: 2.,
+            stone_radius: 14,
+            white_stone_threshold: 190,
+            black_stone_threshold: 60,
+            min_color_threshold: 12,
+            is_dump_steps: true,
+            dump_dir: String::from("./vision_dump/"),
+        }
+    }
+}
+
+impl Settings {
+    pub fn with_dump_dir(mut self, dump_dir: &str) -> Settings {
+        self.dump_dir = String::from(dump_dir);
+        // пути к отладочным картинкам склеиваются с dump_dir без разделителя
+        if !self.dump_dir.ends_with('/') {
+            self.dump_dir.push('/');
+        }
+        self
+    }
+
+    pub fn with_dump_steps(mut self, is_dump_steps: bool) -> Settings {
+        self.is_dump_steps = is_dump_steps;
+        self
+    }
+
+    // Отладочные картинки как в other, остальные настройки свои
+    pub fn with_dumps_of(mut self, other: &Settings) -> Settings {
+        self.is_dump_steps = other.is_dump_steps;
+        self.dump_dir = other.dump_dir.clone();
+        self
+    }
+
+    // Каталог отладочных картинок, если они сохраняются
+    pub fn dump_dir(&self) -> Option<&str> {
+        self.is_dump_steps.then_some(self.dump_dir.as_str())
+    }
+
+    // Значения, с которыми распознавание заведомо не работает
+    pub fn validate(&self) -> std::result::Result<(), String> {
+        if !(0. ..=255.).contains(&self.binary_threshold) {
+            return Err(String::from("binary_threshold must be within 0..=255"));
+        }
+        if self.board_width <= 0 || self.board_height <= 0 {
+            return Err(String::from(
+                "board_width and board_height must be positive",
+            ));
+        }
+        if self.grid_block_size < 3 || self.grid_block_size % 2 == 0 {
+            return Err(String::from("grid_block_size must be odd and at least 3"));
+        }
+        if self.stone_radius <= 0 {
+            return Err(String::from("stone_radius must be positive"));
+        }
+        if self.black_stone_threshold >= self.white_stone_threshold {
+            return Err(String::from(
+                "black_stone_threshold must be below white_stone_threshold",
+            ));
+        }
+        Ok(())
+    }
+}
+
+pub fn convert_to_grayscale(img: &Mat) -> Result<Mat> {
+    // Конвертация в grayscale
+    let mut gray = Mat::default();
+    imgproc::cvt_color(&img, &mut gray, imgproc::COLOR_BGR2GRAY, 0)?;
+    return Ok(gray);
+}
+
+pub fn load_as_bw_from(filename: &str) -> Result<Mat> {
+    let img = opencv::imgcodecs::imread(filename, opencv::imgcodecs::IMREAD_COLOR)?;
+    if img.empty() {
+        panic!("Не удалось загрузить изображение!");
+    }
+
+    convert_to_grayscale(&img)
+}
+
+// Если и возвращает то это полигон с 4мя точками
+pub fn find_board_border(settings: &Settings, img: &Mat) -> Result<Option<Polygon>> {
+    let gray = convert_to_grayscale(img)?;
+    // бинаризация по порогу
+    let mut binary = Mat::default();
+    imgproc::threshold(
+        &gray,
+        &mut binary,
+        settings.binary_threshold,
+        255.0,
+        imgproc::THRESH_BINARY_INV,
+    )?;
+    if settings.is_dump_steps {
+        opencv::imgcodecs::imwrite(
+            &(settings.dump_dir.clone() + "binary.jpg"),
+            &binary,
+            &core::Vector::default(),
+        )?;
+    }
+
+    // Поиск контуров
+    let mut contours: Vector<Polygon> = Vector::new();
+    imgproc::find_contours(
+        &binary,
+        &mut contours,
+        imgproc::RETR_LIST,
+        imgproc::CHAIN_APPROX_SIMPLE,
+        core::Point::default(),
+    )?;
+
+    // ищем самый большой четырёхуголник
+    let mut best_perimeter = std::f64::MIN;
+    let mut best_polygon: Option<Polygon> = Option::None;
+    for contour in contours {
+        let perimeter = imgproc::arc_length(&contour, true)?;
+        // сразу отсекаем полигоны раные всей картинке
+        if perimeter as i32 == (gray.cols() * 2 + gray.rows() * 2) {
+            continue;
+        }
+        let mut polygon: Vector<Point> = Vector::new();
+        // апроксимация полигонов
+        imgproc::approx_poly_dp(&contour, &mut polygon, 0.005 * perimeter, true)?;
+        // поиск четрехугольника
+        if polygon.len() == 4 && imgproc::is_contour_convex(&polygon)? {
+            // полигоны с нулевой точкой это полигоны на весь экран, такое нам не нужно
+            let has_zero_zero_pnt = polygon.iter().any(|pnt| pnt.x == 0 && pnt.y == 0);
+            if has_zero_zero_pnt {
+                continue;
+            }
+            let perimeter = imgproc::arc_length(&polygon, false)?;
+            // с самым большим периметром
+            if perimeter > settings.min_board_border_perimeter && perimeter > best_perimeter {
+                best_perimeter = perimeter;
+                best_polygon = Some(polygon);
+            }
+        }
+    }
+
+    if settings.is_dump_steps {
+        let mut img = Mat::default();
+        imgproc::cvt_color(&gray, &mut img, imgproc::COLOR_GRAY2BGR, 0)?;
+        if let Some(poly) = &best_polygon {
+            // Рисуем контуры на исходном изображении
+            let mut polygon_for_draw: Vector<Vector<Point>> = Vector::new();
+            polygon_for_draw.push(poly.clone());
+            imgproc::draw_contours(
+                &mut img,
+                &polygon_for_draw,
+                -1,                                // Индекс контура (-1 = все контуры)
+                Scalar::new(0.0, 255.0, 0.0, 0.0), // Зелёный цвет
+                2,                                 // Толщина линии
+                imgproc::LINE_8,
+                &Mat::default(),
+                std::i32::MAX,
+                Point::default(),
+            )?;
+        }
+        opencv::imgcodecs::imwrite(
+            &(settings.dump_dir.clone() + "border.jpg"),
+            &img,
+            &core::Vector::default(),
+        )?;
+    }
+    Ok(best_polygon)
+}
+
+pub fn warp_board_by_border(settings: &Settings, border: &Polygon, img: &Mat) -> Result<Mat> {
+    //так как полигон может быть непонятно как развернут то и в который мы придем надо тоже развернуть, возможны проблемы с ромбом
+    let poly_f: Vector<Point2f> = border
+        .iter()
+        .map(|p| Point2f::new(p.x as f32, p.y as f32))
+        .collect();
+    let (sum_x, sum_y) = poly_f
+        .iter()
+        .fold((0., 0.), |(sx, sy), p| (sx + p.x, sy + p.y));
+    let mean_x = sum_x / border.len() as f32;
+    let mean_y = sum_y / border.len() as f32;
+    let dst_poly: Vector<Point2f> = poly_f
+        .iter()
+        .map(|p| {
+            let x = if p.x > mean_x {
+                settings.board_width as f32
+            } else {
+                0.
+            };
+            let y = if p.y > mean_y {
+                settings.board_height as f32
+            } else {
+                0.
+            };
+            Point2f::new(x, y)
+        })
+        .collect();
+
+    let transform_matrix = imgproc::get_perspective_transform(&poly_f, &dst_poly, core::DECOMP_LU)?;
+    let mut warped = Mat::default();
+    imgproc::warp_perspective(
+        &img,
+        &mut warped,
+        &transform_matrix,
+        Size::new(settings.board_width, settings.board_height),
+        imgproc::INTER_LINEAR,
+        core::BORDER_CONSTANT,
+        Scalar::default(),
+    )?;
+    Ok(warped)
+}
+
+// Линии сетки на выровненном изображении доски
+pub struct GridLines {
+    // x вертикальных линий слева направо
+    pub vertical: Vec<f64>,
+    // y горизонтальных линий сверху вниз
+    pub horizontal: Vec<f64>,
+}
+
+impl GridLines {
+    // Равномерная сетка по отступам stones_*_shift из настроек
+    pub fn uniform(settings: &Settings, img: &Mat, width: usize, height: usize) -> GridLines {
+        let horz_shift = settings.stones_left_shift + settings.stones_right_shift;
+        let horz_step = (img.cols() as f64 - horz_shift) / width as f64;
+        let vert_shift = settings.stones_top_shift + settings.stones_bottom_shift;
+        let vert_step = (img.rows() as f64 - vert_shift) / height as f64;
+        GridLines {
+            vertical: (0..width)
+                .map(|x| x as f64 * horz_step + horz_step / 2. + settings.stones_left_shift)
+                .collect(),
+            horizontal: (0..height)
+                .map(|y| y as f64 * vert_step + vert_step / 2. + settings.stones_top_shift)
+                .collect(),
+        }
+    }
+
+    // Центр пересечения в колонке x и строке row, строки считаются сверху изображения
+    pub fn intersection(&self, x: usize, row: usize) -> Point {
+        Point::new(self.vertical[x] as i32, self.horizontal[row] as i32)
+    }
+
+    // Среднее расстояние между соседними линиями по горизонтали и вертикали
+    pub fn steps(&self) -> (f64, f64) {
+        let step = |lines: &[f64]| match lines {
+            [first, .., last] => (last - first) / (lines.len() - 1) as f64,
+            _ => 0.,
+        };
+        (step(&self.vertical), step(&self.horizontal))
+    }
+}
+
+// Подбирает count равноотстоящих линий по профилю проекции: сумме тёмных пикселей линий
+// по каждой строке или колонке. Сначала ищутся шаг и сдвиг, при которых сумма профиля
+// на линиях максимальна, потом каждая линия уточняется до ближайшего пика.
+fn fit_lines(profile: &[f32], count: usize, min_contrast: f64) -> Option<Vec<f64>> {
+    if count < 2 || profile.is_empty() {
+        return None;
+    }
+    let len = profile.len() as f64;
+    let value = |pos: f64| profile.get(pos.round() as usize).copied().unwrap_or(0.) as f64;
+
+    let mut best: Option<(f64, f64, f64)> = None;
+    // доска занимает не меньше половины изображения
+    let min_step = len / 2. / (count - 1) as f64;
+    let max_step = len / (count - 1) as f64;
+    let mut step = min_step;
+    while step <= max_step {
+        let mut offset = 0.;
+        while offset + step * (count - 1) as f64 <= len - 1. {
+            let score: f64 = (0..count).map(|i| value(offset + step * i as f64)).sum();
+            if best.is_none_or(|(best_score, _, _)| score > best_score) {
+                best = Some((score, step, offset));
+            }
+            offset += 1.;
+        }
+        step += 0.25;
+    }
+    let (score, step, offset) = best?;
+
+    // линии должны заметно выделяться на фоне, иначе это не сетка
+    let mean = profile.iter().map(|&v| v as f64).sum::<f64>() / len;
+    if mean <= 0. || score / (count as f64) < mean * min_contrast {
+        return None;
+    }
+
+    let radius = (step / 4.) as i64;
+    let lines = (0..count)
+        .map(|i| {
+            let center = (offset + step * i as f64).round() as i64;
+            (center - radius..=center + radius)
+                .filter(|&pos| pos >= 0 && (pos as usize) < profile.len())
+                // при равных значениях ближайшая к расчётной, чтобы линия без пика не уезжала
+                .max_by(|&a, &b| {
+                    let (da, db) = ((a - center).abs(), (b - center).abs());
+                    profile[a as usize]
+                        .total_cmp(&profile[b as usize])
+                        .then(db.cmp(&da))
+                })
+                .unwrap_or(center) as f64
+        })
+        .collect();
+    Some(lines)
+}
+
+// Профиль проекции тёмных линий одного направления: горизонтальные линии при horizontal,
+// иначе вертикальные. Всё короче 1/20 изображения, в том числе камни и буквы, отбрасывается.
+fn line_profile(binary: &Mat, horizontal: bool) -> Result<Vec<f32>> {
+    let kernel_size = if horizontal {
+        Size::new((binary.cols() / 20).max(1), 1)
+    } else {
+        Size::new(1, (binary.rows() / 20).max(1))
+    };
+    let kernel =
+        imgproc::get_structuring_element(imgproc::MORPH_RECT, kernel_size, Point::new(-1, -1))?;
+    let mut lines = Mat::default();
+    imgproc::morphology_ex(
+        binary,
+        &mut lines,
+        imgproc::MORPH_OPEN,
+        &kernel,
+        Point::new(-1, -1),
+        1,
+        core::BORDER_CONSTANT,
+        imgproc::morphology_default_border_value()?,
+    )?;
+    // сумма по каждой строке для горизонтальных линий, по каждой колонке для вертикальных
+    let mut sums = Mat::default();
+    let dim = if horizontal { 1 } else { 0 };
+    core::reduce(&lines, &mut sums, dim, core::REDUCE_SUM, core::CV_32F)?;
+    let len = if horizontal { sums.rows() } else { sums.cols() };
+    let mut profile = Vec::with_capacity(len as usize);
+    for i in 0..len {
+        profile.push(*sums.at::<f32>(i)?);
+    }
+    Ok(profile)
+}
+
+// Ищет width вертикальных и height горизонтальных линий сетки на выровненном изображении.
+// None, если сетка не нашлась, тогда можно взять GridLines::uniform.
+pub fn find_grid_lines(
+    settings: &Settings,
+    img: &Mat,
+    width: usize,
+    height: usize,
+) -> Result<Option<GridLines>> {
+    let gray = convert_to_grayscale(img)?;
+    // линии тёмные на светлой доске, порог по соседству переживает неровный свет
+    let mut binary = Mat::default();
+    imgproc::adaptive_threshold(
+        &gray,
+        &mut binary,
+        255.0,
+        imgproc::ADAPTIVE_THRESH_MEAN_C,
+        imgproc::THRESH_BINARY_INV,
+        settings.grid_block_size,
+        settings.grid_threshold,
+    )?;
+
+    let vertical = fit_lines(
+        &line_profile(&binary, false)?,
+        width,
+        settings.grid_min_contrast,
+    );
+    let horizontal = fit_lines(
+        &line_profile(&binary, true)?,
+        height,
+        settings.grid_min_contrast,
+    );
+    let (Some(vertical), Some(horizontal)) = (vertical, horizontal) else {
+        return Ok(None);
+    };
+    let lines = GridLines {
+        vertical,
+        horizontal,
+    };
+
+    if settings.is_dump_steps {
+        let mut debug_img = img.clone();
+        let color = Scalar::new(0.0, 255.0, 0.0, 0.0);
+        for &x in &lines.vertical {
+            let top = Point::new(x as i32, 0);
+            let bottom = Point::new(x as i32, img.rows());
+            imgproc::line(&mut debug_img, top, bottom, color, 1, imgproc::LINE_8, 0)?;
+        }
+        for &y in &lines.horizontal {
+            let left = Point::new(0, y as i32);
+            let right = Point::new(img.cols(), y as i32);
+            imgproc::line(&mut debug_img, left, right, color, 1, imgproc::LINE_8, 0)?;
+        }
+        opencv::imgcodecs::imwrite(
+            &(settings.dump_dir.clone() + "grid.jpg"),
+            &debug_img,
+            &core::Vector::default(),
+        )?;
+    }
+    Ok(Some(lines))
+}
+
+// Найденная сетка или, если найти не удалось, равномерная по отступам из настроек
+fn grid_lines(settings: &Settings, img: &Mat, width: usize, height: usize) -> Result<GridLines> {
+    match find_grid_lines(settings, img, width, height)? {
+        Some(lines) => Ok(lines),
+        None => Ok(GridLines::uniform(settings, img, width, height)),
+    }
+}
+
+// Средняя светлота L* и насыщенность цвета в круге вокруг center на изображении в Lab
+fn sample_point(lab: &Mat, mask: &mut Mat, center: Point, radius: i32) -> Result<(u8, u8)> {
+    // Создаём маску для круглой области
+    mask.set_to(&Scalar::all(0.0), &core::no_array())?;
+    imgproc::circle(
+        mask,
+        center,
+        radius,
+        core::Scalar::all(255.0),
+        -1, // Заливка
+        imgproc::LINE_8,
+        0,
+    )?;
+    let mean = core::mean(lab, mask)?;
+    let l = mean[0] as u8;
+    let a = mean[1] as u8;
+    let b = mean[2] as u8;
+    let a = a as f64 - 128.;
+    let b = b as f64 - 128.;
+    let color = (a * a + b * b).sqrt() as u8;
+    Ok((l, color))
+}
+
+pub fn find_stones(settings: &Settings, img: &Mat, board_size: usize) -> Result<Board> {
+    let mut board = Board::new_with_size(board_size);
+    // Создаём маску для круглой области
+    let mut mask = Mat::zeros(img.rows(), img.cols(), core::CV_8UC1)?.to_mat()?;
+
+    let mut debug_img: Option<Mat> = if settings.is_dump_steps {
+        Some(img.clone())
+    } else {
+        None
+    };
+
+    let mut lab = Mat::default();
+    imgproc::cvt_color(&img, &mut lab, imgproc::COLOR_BGR2Lab, 0)?;
+
+    let lines = grid_lines(settings, img, board_size, board_size)?;
+
+    for x in 0..board_size {
+        for y in 0..board_size {
+            let radius = settings.stone_radius; // Радиус круга
+            let center = lines.intersection(x, y);
+            let (l, color) = sample_point(&lab, &mut mask, center, radius)?;
+
+            let pos_y = board_size - y - 1;
+            if l < settings.black_stone_threshold && color <= settings.min_color_threshold {
+                board.set(Position::new(x, pos_y), Cell::black_stone());
+            } else if l > settings.white_stone_threshold && color <= settings.min_color_threshold {
+                board.set(Position::new(x, pos_y), Cell::white_stone());
+            } else {
+                board.set(Position::new(x, pos_y), Cell::empty());
+            }
+
+            if let Some(image) = &mut debug_img {
+                //рисуем кружочки
+                imgproc::circle(
+                    image,
+                    center,
+                    radius,
+                    core::Scalar::new(0.0, 0.0, 255.0, 0.0),
+                    1,
+                    imgproc::LINE_8,
+                    0,
+                )?;
+                // Подписываем значение
+                imgproc::put_text(
+                    image,
+                    &format!("{}/{}", l, color),
+                    core::Point::new(center.x - 20, center.y),
+                    imgproc::FONT_HERSHEY_SIMPLEX,
+                    0.35,
+                    core::Scalar::new(255.0, 0.0, 255.0, 0.0),
+                    1,
+                    imgproc::LINE_AA,
+                    false,
+                )?;
+            }
+        }
+    }
+    if let Some(image) = &debug_img {
+        opencv::imgcodecs::imwrite(
+            &(settings.dump_dir.clone() + "stones.jpg"),
+            &image,
+            &core::Vector::default(),
+        )?;
+    }
+    Ok(board)
+}
+
+// Рисует оценку владения поверх выровненного изображения доски: квадрат на каждой точке,
+// чёрный для чёрных и белый для белых, тем больше, чем увереннее оценка
+pub fn draw_ownership(settings: &Settings, img: &Mat, ownership: &Grid<f32>) -> Result<Mat> {
+    let lines = grid_lines(settings, img, ownership.width(), ownership.height())?;
+    let (horz_step, vert_step) = lines.steps();
+    let mut overlay = img.clone();
+    for (pos, &value) in ownership.iter() {
+        let row = ownership.height() - pos.y() - 1;
+        let center = lines.intersection(pos.x(), row);
+        let half_width = (horz_step / 2. * value.abs() as f64) as i32;
+        let half_height = (vert_step / 2. * value.abs() as f64) as i32;
+        if half_width < 1 || half_height < 1 {
+            continue;
+        }
+        let color = if value > 0. {
+            Scalar::new(0.0, 0.0, 0.0, 0.0)
+        } else {
+            Scalar::new(255.0, 255.0, 255.0, 0.0)
+        };
+        imgproc::rectangle(
+            &mut overlay,
+            Rect::new(
+                center.x - half_width,
+                center.y - half_height,
+                half_width * 2,
+                half_height * 2,
+            ),
+            color,
+            -1, // Заливка
+            imgproc::LINE_8,
+            0,
+        )?;
+    }
+    // полупрозрачно, чтобы камни под оценкой оставались видны
+    let mut res = Mat::default();
+    core::add_weighted(img, 0.4, &overlay, 0.6, 0., &mut res, -1)?;
+    if settings.is_dump_steps {
+        opencv::imgcodecs::imwrite(
+            &(settings.dump_dir.clone() + "ownership.jpg"),
+            &res,
+            &core::Vector::default(),
+        )?;
+    }
+    Ok(res)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    // Профиль длиной len с фоном 1 и пиками 100 в точках peaks
+    fn profile(len: usize, peaks: &[usize]) -> Vec<f32> {
+        let mut profile = vec![1.; len];
+        for &peak in peaks {
+            profile[peak] = 100.;
+        }
+        profile
+    }
+
+    fn even_peaks() -> Vec<usize> {
+        (0..9).map(|i| 10 + 20 * i).collect()
+    }
+
+    #[test]
+    fn evenly_spaced_peaks() {
+        let lines = fit_lines(&profile(200, &even_peaks()), 9, 2.).unwrap();
+        let expected: Vec<f64> = even_peaks().iter().map(|&peak| peak as f64).collect();
+        assert_eq!(lines, expected);
+    }
+
+    #[test]
+    fn missing_and_noisy_peaks() {
+        // нет пика на 90, пик 130 сдвинут на 131, между линиями шум на 60
+        let peaks: Vec<usize> = even_peaks()
+            .into_iter()
+            .filter(|&peak| peak != 90)
+            .map(|peak| if peak == 130 { 131 } else { peak })
+            .collect();
+        let mut profile = profile(200, &peaks);
+        profile[60] = 60.;
+        let lines = fit_lines(&profile, 9, 2.).unwrap();
+        assert_eq!(lines, vec![10., 30., 50., 70., 90., 110., 131., 150., 170.]);
+    }
+
+    #[test]
+    fn more_lines_than_peaks() {
+        let lines = fit_lines(&profile(200, &even_peaks()), 12, 2.).unwrap();
+        assert_eq!(lines.len(), 12);
+        assert!(lines.windows(2).all(|pair| pair[0] < pair[1]));
+        assert!(lines.iter().all(|&line| (0. ..200.).contains(&line)));
+    }
+
+    #[test]
+    fn low_contrast_rejected() {
+        assert_eq!(fit_lines(&[1.; 200], 9, 2.), None);
+        assert_eq!(fit_lines(&[0.; 200], 9, 2.), None);
+        // пики есть, но требуемый контраст выше
+        assert_eq!(fit_lines(&profile(200, &even_peaks()), 9, 50.), None);
+        assert_eq!(fit_lines(&profile(200, &even_peaks()), 1, 2.), None);
+        assert_eq!(fit_lines(&[], 9, 2.), None);
+    }
+}
@@ -0,0 +1,178 @@
+// Весь путь от запуска KataGo до анализа, только вместо KataGo поддельный движок fake_katago
+use robogo::board::{Board, Color, Move, Position};
+use robogo::game::Game;
+use robogo::gtp::{self, Desync, Error, GtpEngine, GtpProcess};
+use robogo::katago::{HumanProfile, Katago, Settings};
+use robogo::sgf;
+use std::fs;
+use std::path::PathBuf;
+use std::time::Duration;
+
+const FAKE: &str = env!("CARGO_BIN_EXE_fake_katago");
+
+fn pos(s: &str) -> Position {
+    Position::from_gtp(s).unwrap()
+}
+
+// Настройки как из файла, без лога в ./vision_dump
+fn settings(args: &[&str]) -> Settings {
+    let settings: Settings =
+        toml::from_str("dump_to_filename = false\ncommand_timeout = 10").unwrap();
+    settings.with_program(".", FAKE).with_args(args)
+}
+
+fn start(args: &[&str]) -> Katago {
+    let mut katago = Katago::new(settings(args)).unwrap();
+    katago.wait_gtp_ready().unwrap();
+    katago.boardsize(9).unwrap();
+    katago
+}
+
+// Файл-метка для -crash-once, у каждого теста свой
+fn crash_marker(name: &str) -> PathBuf {
+    let path = PathBuf::from(env!("CARGO_TARGET_TMPDIR")).join(name);
+    fs::write(&path, "").unwrap();
+    path
+}
+
+#[test]
+fn scripted_genmove_and_sync() {
+    let mut katago = start(&["-moves", "D4,pass"]);
+    katago.play(Color::Black, Move::Play(pos("E5"))).unwrap();
+    assert_eq!(katago.genmove(Color::White).unwrap(), Move::Play(pos("D4")));
+    katago.play(Color::Black, Move::Play(pos("C3"))).unwrap();
+    assert_eq!(katago.genmove(Color::White).unwrap(), Move::Pass);
+
+    let mut board = Board::new_with_size(9);
+    board.play(Color::Black, pos("E5")).unwrap();
+    board.play(Color::White, pos("D4")).unwrap();
+    board.play(Color::Black, pos("C3")).unwrap();
+    board.pass(Color::White);
+    let state = katago.check_sync(&board).unwrap();
+    assert_eq!(state.move_num, 4);
+    assert_eq!(state.next_move, Color::Black);
+}
+
+#[test]
+fn illegal_move_is_rejected() {
+    let mut katago = start(&[]);
+    katago.play(Color::Black, Move::Play(pos("E5"))).unwrap();
+    assert!(matches!(
+        katago.play(Color::White, Move::Play(pos("E5"))),
+        Err(Error::UnknownError(_))
+    ));
+}
+
+#[test]
+fn check_sync_reports_desync() {
+    let mut katago = start(&[]);
+    katago.play(Color::Black, Move::Play(pos("E5"))).unwrap();
+
+    let mut board = Board::new_with_size(9);
+    board.play(Color::Black, pos("D5")).unwrap();
+    match katago.check_sync(&board) {
+        Err(Error::Desync(Desync::Stones(stones))) => assert_eq!(stones.len(), 2),
+        _ => panic!("expected stones desync"),
+    }
+    assert!(matches!(
+        katago.check_sync(&Board::new_with_size(19)),
+        Err(Error::Desync(Desync::Size { .. }))
+    ));
+}
+
+#[test]
+fn analyze_stops_on_callback() {
+    let mut katago = start(&[]);
+    let mut updates = 0;
+    katago
+        .analyze(Color::Black, Duration::from_millis(10), |infos| {
+            assert_eq!(infos[0].order, 0);
+            updates += 1;
+            updates < 3
+        })
+        .unwrap();
+    assert_eq!(updates, 3);
+    // после остановки анализа движок отвечает на обычные команды
+    assert!(katago.known_command("showboard").unwrap());
+}
+
+#[test]
+fn ownership_is_from_black_side() {
+    let mut katago = start(&[]);
+    katago.play(Color::Black, Move::Play(pos("C3"))).unwrap();
+    katago.play(Color::White, Move::Play(pos("G7"))).unwrap();
+    let ownership = katago
+        .ownership(Color::White, Duration::from_millis(100))
+        .unwrap();
+    assert_eq!(ownership.get(pos("C3")), Some(&1.));
+    assert_eq!(ownership.get(pos("G7")), Some(&-1.));
+    assert_eq!(ownership.get(pos("E5")), Some(&0.));
+}
+
+#[test]
+fn slow_genmove_times_out() {
+    let mut katago = start(&["-think", "1000"]);
+    katago.play(Color::Black, Move::Play(pos("E5"))).unwrap();
+    let result = katago.send_with_timeout("genmove w", Some(Duration::from_millis(100)));
+    assert!(matches!(result, Err(Error::Timeout)));
+    assert!(katago.known_command("play").unwrap());
+
+    // движок перезапущен с позицией до genmove, его ход не попал на доску
+    let mut board = Board::new_with_size(9);
+    board.play(Color::Black, pos("E5")).unwrap();
+    let state = katago.check_sync(&board).unwrap();
+    assert_eq!(state.move_num, 1);
+    assert_eq!(state.next_move, Color::White);
+}
+
+#[test]
+fn crash_without_restart_is_reported() {
+    let marker = crash_marker("engine_exited");
+    let settings = gtp::Settings::new(".", FAKE, &["-crash-once", marker.to_str().unwrap()])
+        .restart_on_crash(false)
+        .ready_line("GTP ready");
+    let mut engine = GtpProcess::new(settings).unwrap();
+    engine.wait_ready().unwrap();
+    match engine.genmove(Color::Black) {
+        Err(Error::EngineExited(status)) => assert!(!status.success()),
+        _ => panic!("expected EngineExited"),
+    }
+}
+
+#[test]
+fn crash_restarts_and_replays_game() {
+    let marker = crash_marker("restart");
+    let mut katago = start(&["-moves", "G7", "-crash-once", marker.to_str().unwrap()]);
+    katago.komi(6.5).unwrap();
+    katago.play(Color::Black, Move::Play(pos("C3"))).unwrap();
+    // движок падает на genmove, перезапускается, повторяет партию и ходит заново
+    assert_eq!(katago.genmove(Color::White).unwrap(), Move::Play(pos("G7")));
+    assert!(!marker.exists());
+
+    let mut board = Board::new_with_size(9);
+    board.play(Color::Black, pos("C3")).unwrap();
+    board.play(Color::White, pos("G7")).unwrap();
+    let state = katago.check_sync(&board).unwrap();
+    assert_eq!(state.move_num, 2);
+}
+
+#[test]
+fn restart_keeps_human_profile() {
+    let marker = crash_marker("params");
+    let mut katago = start(&["-crash-once", marker.to_str().unwrap()]);
+    let profile: HumanProfile = "rank_5k".parse().unwrap();
+    katago.set_human_profile(profile).unwrap();
+    katago.genmove(Color::Black).unwrap();
+    assert!(!marker.exists());
+    let value = katago.send("kata-get-param humanSLProfile").unwrap();
+    assert_eq!(value.trim(), "rank_5k");
+}
+
+#[test]
+fn sgf_setup_stones_are_synced() {
+    let mut katago = start(&[]);
+    let tree = sgf::parse("(;SZ[9]KM[6.5]AB[cc][gg]AW[cg][ee];B[dd])").unwrap();
+    let mut game = Game::from_sgf(&tree).unwrap();
+    game.sync(&mut katago).unwrap();
+    katago.check_sync(game.board()).unwrap();
+}